@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use phys_engine::engine::{self, AdapterSelection, InstanceOptions};
+use winit::dpi::PhysicalSize;
+
+/// A GPU particle simulation.
+///
+/// Keys: C colour mode, M colour map, R render mode, H HDR, B bloom, T trails,
+/// I integrator, A adaptive timestep, V present mode, L frame limit, F3 HUD,
+/// F4 diagnostics, F5 reload the scene, F6 watch shaders, F9 save frame times,
+/// F10 record, F12 screenshot. Scroll to zoom, drag with the right or middle button
+/// to pan, Home resets the view.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Args
+{
+    /// Scene file to load, see `scenes/` for examples
+    pub scene : Option<PathBuf>,
+
+    /// Number of particles, spread over the scene's groups in proportion to their counts
+    #[arg(short = 'n', long)]
+    pub count : Option<usize>,
+
+    /// Integrator to run the scene with, instead of the one it names
+    #[arg(long, value_enum)]
+    pub integrator : Option<Integrator>,
+
+    /// Shorten steps while particles move fast, when the scene doesn't set it up itself
+    #[arg(long)]
+    pub adaptive : bool,
+
+    /// Seed for the scene's random groups, the nth group gets SEED + n
+    #[arg(long)]
+    pub seed : Option<u64>,
+
+    /// Window size, or the frame size when headless
+    #[arg(long, value_name = "WIDTHxHEIGHT", default_value = "200x200", value_parser = parse_size)]
+    pub size : PhysicalSize<u32>,
+
+    #[arg(long)]
+    pub fullscreen : bool,
+
+    #[arg(long, value_enum, default_value_t = Backend::Vulkan)]
+    pub backend : Backend,
+
+    /// Adapter to run on, by its index or part of its name, see --list-adapters
+    #[arg(long, value_name = "INDEX|NAME")]
+    pub adapter : Option<String>,
+
+    /// List the adapters of the backend and exit
+    #[arg(long)]
+    pub list_adapters : bool,
+
+    #[arg(long, value_enum, default_value_t = Power::High)]
+    pub power : Power,
+
+    /// Fail instead of trying other adapters and backends when the requested one isn't available
+    #[arg(long)]
+    pub no_fallback : bool,
+
+    /// How frames are presented, falling back to Fifo when the surface doesn't support it
+    #[arg(long, value_enum, default_value_t = PresentMode::Fifo)]
+    pub present_mode : PresentMode,
+
+    /// Frames per second to stay under, headless runs included
+    #[arg(long, value_name = "FPS")]
+    pub fps_limit : Option<f32>,
+
+    /// Run this many steps without a window, then save a snapshot and exit
+    #[arg(long, value_name = "STEPS")]
+    pub headless : Option<u64>,
+
+    /// Directory screenshots, recordings, snapshots and frame times are saved in
+    #[arg(short, long, default_value = ".")]
+    pub output : PathBuf,
+
+    /// Sum energy and momentum every step, shown in the HUD and logged when headless
+    #[arg(long)]
+    pub diagnostics : bool,
+
+    /// Image whose red and green channels push the particles, stretched over the scene's boundary
+    #[arg(long, value_name = "IMAGE")]
+    pub field : Option<PathBuf>,
+
+    /// Forces black and white map to in the field image
+    #[arg(long, value_name = "MIN,MAX", default_value = "-1,1", value_parser = parse_range, allow_hyphen_values = true)]
+    pub field_range : [f32; 2],
+
+    /// Reload the WGSL shaders in src/engine whenever they are saved
+    #[arg(long)]
+    pub watch_shaders : bool,
+
+    /// Save every Nth step as a PNG in OUTPUT/frames, from the first step
+    #[arg(long, value_name = "N")]
+    pub record_every : Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Backend
+{
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+    /// Vulkan, DX12, Metal or WebGPU, whichever the platform has
+    Primary,
+    All,
+}
+
+/// Which GPU is preferred when there are several
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Power
+{
+    /// Usually an integrated GPU
+    Low,
+    /// Usually a discrete GPU
+    High,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Integrator
+{
+    Verlet,
+    Leapfrog,
+    Rk4,
+}
+
+impl From<Integrator> for engine::Integrator
+{
+    fn from(integrator : Integrator) -> Self
+    {
+        match integrator
+        {
+            Integrator::Verlet => engine::Integrator::Verlet,
+            Integrator::Leapfrog => engine::Integrator::Leapfrog,
+            Integrator::Rk4 => engine::Integrator::Rk4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PresentMode
+{
+    /// No vsync, may tear
+    Immediate,
+    /// No vsync or tearing, drops frames
+    Mailbox,
+    /// Vsync
+    Fifo,
+    /// Vsync, tears when a frame is late
+    FifoRelaxed,
+}
+
+impl Args
+{
+    pub fn backends(&self) -> wgpu::Backends
+    {
+        match self.backend
+        {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::Primary => wgpu::Backends::PRIMARY,
+            Backend::All => wgpu::Backends::all(),
+        }
+    }
+
+    pub fn instance_options(&self) -> InstanceOptions
+    {
+        InstanceOptions {
+            backends: self.backends(),
+            power_preference: match self.power
+            {
+                Power::Low => wgpu::PowerPreference::LowPower,
+                Power::High => wgpu::PowerPreference::HighPerformance,
+            },
+            adapter: self.adapter.as_deref().map_or(AdapterSelection::Auto, AdapterSelection::parse),
+            fallback: !self.no_fallback,
+            present_mode: match self.present_mode
+            {
+                PresentMode::Immediate => wgpu::PresentMode::Immediate,
+                PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+                PresentMode::Fifo => wgpu::PresentMode::Fifo,
+                PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            },
+            output_dir: self.output.clone(),
+        }
+    }
+}
+
+fn parse_size(s : &str) -> Result<PhysicalSize<u32>, String>
+{
+    let (width, height) = s.split_once('x').ok_or("expected WIDTHxHEIGHT, like 1280x720")?;
+    let parse = |n : &str| match n.trim().parse::<u32>()
+    {
+        Ok(0) => Err("sizes must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("{}: {}", n, e)),
+    };
+    Ok(PhysicalSize::new(parse(width)?, parse(height)?))
+}
+
+fn parse_range(s : &str) -> Result<[f32; 2], String>
+{
+    let (min, max) = s.split_once(',').ok_or("expected MIN,MAX, like -1,1")?;
+    let parse = |n : &str| n.trim().parse::<f32>().map_err(|e| format!("{}: {}", n, e));
+    Ok([parse(min)?, parse(max)?])
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use wgpu::{Adapter, AdapterInfo, Backends, DeviceType, PowerPreference, Surface};
+use winit::window::Window;
+
+use super::InstanceOptions;
+use crate::Error;
+
+/// Which adapter an [`Instance`](super::Instance) runs on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AdapterSelection {
+    /// The one wgpu prefers for the power preference.
+    #[default]
+    Auto,
+    /// The adapter at this index of [`list_adapters`].
+    Index(usize),
+    /// The first adapter whose name contains this, ignoring case.
+    Name(String),
+}
+
+impl AdapterSelection {
+    /// An index when `s` is a number, otherwise a name.
+    pub fn parse(s: &str) -> Self {
+        match s.trim().parse() {
+            Ok(index) => AdapterSelection::Index(index),
+            Err(_) => AdapterSelection::Name(s.trim().to_string()),
+        }
+    }
+
+    fn matches(&self, index: usize, info: &AdapterInfo) -> bool {
+        match self {
+            AdapterSelection::Auto => true,
+            AdapterSelection::Index(i) => *i == index,
+            AdapterSelection::Name(name) => {
+                info.name.to_lowercase().contains(&name.to_lowercase())
+            }
+        }
+    }
+}
+
+/// Every adapter on `backends`, in the order [`AdapterSelection::Index`] counts them.
+pub fn list_adapters(backends: Backends) -> Vec<AdapterInfo> {
+    let instance = create_instance(backends);
+    instance
+        .enumerate_adapters(backends)
+        .iter()
+        .map(Adapter::get_info)
+        .collect()
+}
+
+/// One line describing `info`, as listed and logged.
+pub fn describe_adapter(info: &AdapterInfo) -> String {
+    format!(
+        "{} ({:?}, {:?}{})",
+        info.name,
+        info.backend,
+        info.device_type,
+        match info.driver.as_str() {
+            "" => String::new(),
+            driver => format!(", {} {}", driver, info.driver_info),
+        }
+    )
+}
+
+fn create_instance(backends: Backends) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    })
+}
+
+/// Finds an adapter for `options`, along with a surface for `window` it can present to and
+/// the wgpu instance both came from, which later surfaces are created with.
+///
+/// When the selected adapter can't be found wgpu picks one, then its software fallback
+/// adapter is tried, and finally every other backend. The fallbacks are skipped when
+/// `options.fallback` is off.
+pub(crate) async fn open(
+    options: &InstanceOptions,
+    window: Option<Arc<Window>>,
+) -> Result<(wgpu::Instance, Adapter, Option<Surface<'static>>), Error> {
+    let mut attempts = vec![options.backends];
+    if options.fallback && options.backends != Backends::all() {
+        attempts.push(Backends::all() - options.backends);
+    }
+
+    let mut surface_error = None;
+    let mut surface_created = false;
+    for backends in attempts {
+        let instance = create_instance(backends);
+        let surface = match &window {
+            Some(window) => match instance.create_surface(window.clone()) {
+                Ok(surface) => {
+                    surface_created = true;
+                    Some(surface)
+                }
+                Err(e) => {
+                    log::warn!("No surface on {:?}: {}", backends, e);
+                    surface_error = Some(e);
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        if let Some(adapter) = request(&instance, backends, options, surface.as_ref()).await {
+            let info = adapter.get_info();
+            log::info!("Using {}", describe_adapter(&info));
+            if info.device_type == DeviceType::Cpu {
+                log::warn!("Running on a software adapter, expect it to be slow");
+            }
+            return Ok((instance, adapter, surface));
+        }
+        log::warn!("No usable adapter on {:?}", backends);
+    }
+
+    // Without any surface there was nothing to look for an adapter with
+    Err(match surface_error {
+        Some(e) if !surface_created => Error::Surface(e),
+        _ => Error::NoAdapter {
+            backends: options.backends,
+        },
+    })
+}
+
+async fn request(
+    instance: &wgpu::Instance,
+    backends: Backends,
+    options: &InstanceOptions,
+    surface: Option<&Surface<'_>>,
+) -> Option<Adapter> {
+    // Indices count the adapters of the requested backends, the fallback backends pick freely
+    if options.adapter != AdapterSelection::Auto && backends == options.backends {
+        let selected = instance
+            .enumerate_adapters(backends)
+            .into_iter()
+            .enumerate()
+            .find(|(i, adapter)| options.adapter.matches(*i, &adapter.get_info()))
+            .map(|(_, adapter)| adapter);
+
+        match selected {
+            Some(adapter) if surface.is_none_or(|s| adapter.is_surface_supported(s)) => {
+                return Some(adapter)
+            }
+            Some(adapter) => log::warn!(
+                "{} can't present to the window",
+                describe_adapter(&adapter.get_info())
+            ),
+            None => log::warn!("No adapter matches {:?}", options.adapter),
+        }
+        if !options.fallback {
+            return None;
+        }
+    }
+
+    let request = |power_preference, force_fallback_adapter| {
+        instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference,
+            compatible_surface: surface,
+            force_fallback_adapter,
+        })
+    };
+
+    if let Some(adapter) = request(options.power_preference, false).await {
+        return Some(adapter);
+    }
+    if !options.fallback {
+        return None;
+    }
+    request(PowerPreference::None, true).await
+}
@@ -10,6 +10,9 @@ use winit::dpi::PhysicalSize;
 
 use crate::SIDE_LENGTH;
 
+/// How far [`Camera::zoom_at`] zooms out and in.
+const ZOOM_RANGE: (f32, f32) = (0.01, 1000.);
+
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: Mat4 = Mat4::from_array(
     [  
@@ -40,6 +43,8 @@ impl CameraUniform {
 
 pub struct Camera {
     pub eye: Vector,
+    /// Magnification, `1` fits the simulation across the width of the screen.
+    pub zoom: f32,
     pub width: f32,
     pub height: f32,
 
@@ -67,6 +72,7 @@ impl Camera {
 
         Self {
             eye: Vector::new3(0., 0., -2.),
+            zoom: 1.,
             width: size.width as f32,
             height: size.height as f32,
             buffer,
@@ -117,9 +123,38 @@ impl Camera {
         let view = Mat4::new_translation(self.eye * -1.);
         let aspect = self.height / self.width;
 
-        let projection = Mat4::new_orthographic_matrix(0., SIDE_LENGTH as f32, 0., SIDE_LENGTH as f32 / aspect, 0.1, 10.);
+        let extent = SIDE_LENGTH as f32 / self.zoom;
+        let projection = Mat4::new_orthographic_matrix(0., extent, 0., extent / aspect, 0.1, 10.);
         // let view = Mat4::new_perspective_matrix(1., 1., 40., 0.1, 100.);
 
         projection * view
     }
+
+    /// The world position under a pixel, measured from the top left of the screen.
+    pub fn screen_to_world(&self, x: f32, y: f32) -> [f32; 2] {
+        let extent = SIDE_LENGTH as f32 / self.zoom;
+        let aspect = self.height / self.width;
+        [
+            self.eye.x + x / self.width * extent,
+            self.eye.y + (1. - y / self.height) * extent / aspect,
+        ]
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the world position under the pixel
+    /// `(x, y)` where it is on screen.
+    pub fn zoom_at(&mut self, factor: f32, x: f32, y: f32) {
+        let before = self.screen_to_world(x, y);
+        self.zoom = (self.zoom * factor).clamp(ZOOM_RANGE.0, ZOOM_RANGE.1);
+        let after = self.screen_to_world(x, y);
+        self.eye.x += before[0] - after[0];
+        self.eye.y += before[1] - after[1];
+    }
+
+    /// Moves the view along with a cursor dragged by `(dx, dy)` pixels.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let origin = self.screen_to_world(0., 0.);
+        let moved = self.screen_to_world(dx, dy);
+        self.eye.x -= moved[0] - origin[0];
+        self.eye.y -= moved[1] - origin[1];
+    }
 }
@@ -0,0 +1,192 @@
+use std::sync::{Arc, Mutex};
+
+use image::RgbaImage;
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, Device, ImageCopyBuffer,
+    ImageCopyTexture, ImageDataLayout, Maintain, MapMode, Origin3d, Queue, Texture, TextureAspect,
+    TextureDescriptor, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+use winit::dpi::PhysicalSize;
+
+use super::shaders::catch_errors;
+use crate::Error;
+
+/// The outcome of the last `map_async` of a readback buffer, left by its callback until
+/// the frame loop picks it up.
+#[derive(Clone, Default)]
+pub(crate) struct MapResult(Arc<Mutex<Option<Result<(), BufferAsyncError>>>>);
+
+impl MapResult {
+    /// Starts mapping all of `buffer` for reading.
+    pub fn map(&self, buffer: &Buffer) {
+        let result = self.0.clone();
+        buffer.slice(..).map_async(MapMode::Read, move |r| {
+            *result.lock().unwrap() = Some(r);
+        });
+    }
+
+    /// Whether `buffer` was mapped, once the map has finished. A failure is logged and the
+    /// buffer unmapped, so it can be copied into again.
+    pub fn take(&self, buffer: &Buffer, device: &Device, what: &str) -> Option<bool> {
+        Some(match self.take_result(buffer, device)? {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Failed to read back the {}: {}", what, e);
+                false
+            }
+        })
+    }
+
+    /// Like [`MapResult::take`], handing the failure back instead of logging it.
+    pub fn take_result(
+        &self,
+        buffer: &Buffer,
+        device: &Device,
+    ) -> Option<Result<(), BufferAsyncError>> {
+        let result = self.0.lock().unwrap().take()?;
+        if result.is_err() {
+            // A failed map usually leaves the buffer unmapped already, which unmap reports
+            let _ = catch_errors(device, || buffer.unmap());
+        }
+        Some(result)
+    }
+}
+
+/// A texture that can be rendered into and read back to the CPU.
+pub struct OffscreenTarget {
+    texture: Texture,
+    view: TextureView,
+    size: PhysicalSize<u32>,
+    format: TextureFormat,
+}
+
+impl OffscreenTarget {
+    /// Fails when `size` is empty or larger than the biggest texture of the device.
+    pub fn new(
+        size: PhysicalSize<u32>,
+        format: TextureFormat,
+        device: &Device,
+    ) -> Result<Self, Error> {
+        let max = device.limits().max_texture_dimension_2d;
+        if size.width == 0 || size.height == 0 {
+            return Err(Error::CaptureSize {
+                requested: size,
+                available: PhysicalSize::new(max, max),
+            });
+        }
+        for (limit, required) in [
+            ("capture width", size.width),
+            ("capture height", size.height),
+        ] {
+            if required > max {
+                return Err(Error::LimitsExceeded {
+                    limit,
+                    required: required as u64,
+                    supported: max as u64,
+                });
+            }
+        }
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        Ok(Self {
+            texture,
+            view,
+            size,
+            format,
+        })
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// Copies the texture back to the CPU, blocking until the GPU is done with it.
+    pub fn read(&self, device: &Device, queue: &Queue) -> Result<RgbaImage, Error> {
+        let unpadded_bytes_per_row = self.size.width * 4;
+        let bytes_per_row = unpadded_bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: bytes_per_row as u64 * self.size.height as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(self.size.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.size.width,
+                height: self.size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = MapResult::default();
+        mapped.map(&buffer);
+        device.poll(Maintain::Wait);
+        match mapped.take_result(&buffer, device) {
+            Some(Ok(())) => {}
+            Some(Err(e)) => return Err(Error::Readback(e.to_string())),
+            None => return Err(Error::Readback("the map never finished".to_string())),
+        }
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.size.height) as usize);
+        for row in buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks(bytes_per_row as usize)
+        {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        buffer.unmap();
+
+        if matches!(
+            self.format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(self.size.width, self.size.height, pixels).ok_or_else(|| {
+            Error::Readback(format!(
+                "the pixels don't fill {}x{}",
+                self.size.width, self.size.height
+            ))
+        })
+    }
+}
@@ -0,0 +1,552 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt, TextureDataOrder},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferDescriptor, BufferSlice, BufferUsages, CommandEncoder,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, FilterMode,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, Queue, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, TextureDescriptor, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, Sampler, vertex_attr_array, VertexAttribute,
+    VertexBufferLayout, VertexStepMode,
+};
+
+use crate::{Error, SIDE_LENGTH};
+
+use super::{
+    compute::workgroups,
+    profiler::{GpuPass, GpuProfiler},
+};
+
+/// Number of texels in the colour map lookup texture.
+const COLOUR_MAP_SIZE: u32 = 256;
+
+/// Size of a density grid cell in world units.
+const DENSITY_CELL_SIZE: f32 = 8.;
+
+/// The per-particle quantity used to pick a colour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourMode {
+    /// Use the sprite tinted by the particle's own colour.
+    Sprite,
+    Speed,
+    KineticEnergy,
+    /// Number of particles sharing a density grid cell.
+    Density,
+    Species,
+    /// A scalar supplied with [`ParticleColouring::set_custom_scalars`].
+    Custom,
+}
+
+impl ColourMode {
+    const ALL: [ColourMode; 6] = [
+        ColourMode::Sprite,
+        ColourMode::Speed,
+        ColourMode::KineticEnergy,
+        ColourMode::Density,
+        ColourMode::Species,
+        ColourMode::Custom,
+    ];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|m| *m == self).unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    fn id(self) -> u32 {
+        match self {
+            ColourMode::Sprite => 0,
+            ColourMode::Speed => 1,
+            ColourMode::KineticEnergy => 2,
+            ColourMode::Density => 3,
+            ColourMode::Species => 4,
+            ColourMode::Custom => 5,
+        }
+    }
+}
+
+/// How scalars are mapped onto the `[0, 1]` range of the colour map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColourRange {
+    /// Use the minimum and maximum of the current frame.
+    Auto,
+    Fixed(f32, f32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColourMap {
+    Viridis,
+    Magma,
+    Turbo,
+    /// A user supplied gradient, sRGB texels from low to high.
+    Gradient(Vec<[u8; 4]>),
+}
+
+impl ColourMap {
+    /// Loads a gradient from an image, the first row is used from left to right.
+    pub fn from_image(path: impl AsRef<std::path::Path>) -> image::ImageResult<Self> {
+        let image = image::open(path)?.to_rgba8();
+        Ok(ColourMap::Gradient(
+            (0..image.width()).map(|x| image.get_pixel(x, 0).0).collect(),
+        ))
+    }
+
+    /// Cycles through the built-in colour maps.
+    pub fn next(&self) -> Self {
+        match self {
+            ColourMap::Viridis => ColourMap::Magma,
+            ColourMap::Magma => ColourMap::Turbo,
+            _ => ColourMap::Viridis,
+        }
+    }
+
+    fn texels(&self) -> Vec<[u8; 4]> {
+        match self {
+            ColourMap::Gradient(texels) if !texels.is_empty() => return texels.clone(),
+            _ => {}
+        }
+
+        (0..COLOUR_MAP_SIZE)
+            .map(|i| {
+                let t = i as f32 / (COLOUR_MAP_SIZE - 1) as f32;
+                let [r, g, b] = match self {
+                    ColourMap::Viridis => polynomial(&VIRIDIS, t),
+                    ColourMap::Magma => polynomial(&MAGMA, t),
+                    // An empty gradient falls back to turbo
+                    _ => polynomial(&TURBO, t),
+                };
+                [to_u8(r), to_u8(g), to_u8(b), 255]
+            })
+            .collect()
+    }
+}
+
+fn to_u8(c: f32) -> u8 {
+    (c.clamp(0., 1.) * 255.).round() as u8
+}
+
+fn polynomial(coefficients: &[[f32; 3]; 7], t: f32) -> [f32; 3] {
+    let mut out = [0.; 3];
+    for (c, o) in out.iter_mut().enumerate() {
+        *o = coefficients.iter().rev().fold(0., |acc, k| acc * t + k[c]);
+    }
+    out
+}
+
+// Polynomial fits of the matplotlib colour maps, lowest order first
+// https://www.shadertoy.com/view/WlfXRN
+#[rustfmt::skip]
+const VIRIDIS: [[f32; 3]; 7] = [
+    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+    [0.105_093_04, 1.404_613_5, 1.384_590_1],
+    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+    [-4.634_230_6, -5.799_101, -19.332_441],
+    [6.228_27, 14.179_933, 56.690_55],
+    [4.776_385, -13.745_145, -65.353_035],
+    [-5.435_456, 4.645_852_6, 26.312_435],
+];
+
+#[rustfmt::skip]
+const MAGMA: [[f32; 3]; 7] = [
+    [-0.002_136_485, -0.000_749_655_05, -0.005_386_128],
+    [0.251_660_54, 0.677_523_2, 2.494_026_7],
+    [8.353_717, -3.577_719_4, 0.314_467_9],
+    [-27.668_733, 14.264_731, -13.649_213],
+    [52.176_14, -27.943_607, 12.944_169],
+    [-50.768_524, 29.046_583, 4.234_153],
+    [18.655_705, -11.489_774, -5.601_961_4],
+];
+
+// https://ai.googleblog.com/2019/08/turbo-improved-rainbow-colormap-for.html
+#[rustfmt::skip]
+const TURBO: [[f32; 3]; 7] = [
+    [0.135_721_38, 0.091_402_61, 0.106_673_3],
+    [4.615_392_6, 2.194_188_4, 12.641_946],
+    [-42.660_324, 4.842_966_6, -60.582_05],
+    [132.131_08, -14.185_033, 110.362_77],
+    [-152.942_4, 4.277_298_7, -89.903_11],
+    [59.286_38, 2.829_566, 27.348_25],
+    [0., 0., 0.],
+];
+
+/// Uploads a colour map as a 1D texture.
+pub(crate) fn colour_map_view(map: &ColourMap, device: &Device, queue: &Queue) -> TextureView {
+    let texels = map.texels();
+    let texture = device.create_texture_with_data(
+        queue,
+        &TextureDescriptor {
+            label: Some("Colour Map Texture"),
+            size: wgpu::Extent3d {
+                width: texels.len().max(1) as u32,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D1,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        TextureDataOrder::LayerMajor,
+        bytemuck::cast_slice(&texels),
+    );
+    texture.create_view(&TextureViewDescriptor::default())
+}
+
+pub(crate) fn colour_map_sampler(device: &Device) -> Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        address_mode_u: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct ColourUniforms {
+    mode: u32,
+    count: u32,
+    grid_width: u32,
+    grid_height: u32,
+    cell_size: f32,
+    timestep: f32,
+    fixed_range: u32,
+    _padding: u32,
+    range: [f32; 2],
+    _padding_2: [f32; 2],
+}
+
+/// Computes a scalar per particle on the GPU and maps it through a colour map.
+///
+/// The normalised scalars are bound as a second instance buffer, the vertex shader
+/// looks them up in the colour map texture bound by [`ParticleColouring::group`].
+pub struct ParticleColouring {
+    mode: ColourMode,
+    range: ColourRange,
+    map: ColourMap,
+
+    uniforms: ColourUniforms,
+    uniform_buffer: Buffer,
+    scalar_buffer: Buffer,
+    custom_buffer: Buffer,
+
+    compute_bind_group: BindGroup,
+    clear_pipeline: ComputePipeline,
+    splat_pipeline: ComputePipeline,
+    scalar_pipeline: ComputePipeline,
+    normalise_pipeline: ComputePipeline,
+
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+}
+
+impl ParticleColouring {
+    /// `timestep` is the one the simulation stores velocities over, for speed and energy.
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        particles: &Buffer,
+        count: u32,
+        timestep: f32,
+    ) -> Self {
+        let grid_size = (SIDE_LENGTH as f32 / DENSITY_CELL_SIZE).ceil() as u32;
+        let uniforms = ColourUniforms {
+            mode: ColourMode::Sprite.id(),
+            count,
+            grid_width: grid_size,
+            grid_height: grid_size,
+            cell_size: DENSITY_CELL_SIZE,
+            timestep,
+            fixed_range: 0,
+            _padding: 0,
+            range: [0., 1.],
+            _padding_2: [0.; 2],
+        };
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Colour Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniforms]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let scalar_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Colour Scalar Buffer"),
+            size: count.max(1) as u64 * 4,
+            usage: BufferUsages::VERTEX | BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let custom_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Colour Custom Scalar Buffer"),
+            size: count.max(1) as u64 * 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Density cells followed by the encoded minimum and maximum of the frame
+        let density_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Colour Density Buffer"),
+            size: (grid_size as u64 * grid_size as u64 + 2) * 4,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let compute_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Colour Compute Bind Group Layout"),
+            entries: &[
+                storage(0, true),
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(2, false),
+                storage(3, true),
+                storage(4, false),
+            ],
+        });
+
+        let compute_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Colour Compute Bind Group"),
+            layout: &compute_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: particles.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: scalar_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: custom_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: density_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("colour_compute.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Colour Compute Layout"),
+            bind_group_layouts: &[&compute_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+                compilation_options: PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Colour Map Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D1,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let map = ColourMap::Viridis;
+        let bind_group =
+            Self::create_bind_group(&map, &uniform_buffer, &bind_group_layout, device, queue);
+
+        Self {
+            mode: ColourMode::Sprite,
+            range: ColourRange::Auto,
+            map,
+            uniforms,
+            uniform_buffer,
+            scalar_buffer,
+            custom_buffer,
+            compute_bind_group,
+            clear_pipeline: pipeline("clear"),
+            splat_pipeline: pipeline("splat"),
+            scalar_pipeline: pipeline("scalar"),
+            normalise_pipeline: pipeline("normalise"),
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    fn create_bind_group(
+        map: &ColourMap,
+        uniform_buffer: &Buffer,
+        layout: &BindGroupLayout,
+        device: &Device,
+        queue: &Queue,
+    ) -> BindGroup {
+        let view = colour_map_view(map, device, queue);
+        let sampler = colour_map_sampler(device);
+
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Colour Map Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        })
+    }
+
+    /// Layout of the normalised scalar buffer, bound next to the particle buffer.
+    pub fn desc() -> VertexBufferLayout<'static> {
+        const ATTRIB: [VertexAttribute; 1] = vertex_attr_array![12 => Float32];
+        VertexBufferLayout {
+            step_mode: VertexStepMode::Instance,
+            array_stride: std::mem::size_of::<f32>() as wgpu::BufferAddress,
+            attributes: &ATTRIB,
+        }
+    }
+
+    pub fn group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn get_scalar_buffer(&self) -> BufferSlice {
+        self.scalar_buffer.slice(..)
+    }
+
+    pub fn mode(&self) -> ColourMode {
+        self.mode
+    }
+
+    pub fn range(&self) -> ColourRange {
+        self.range
+    }
+
+    pub fn map(&self) -> &ColourMap {
+        &self.map
+    }
+
+    pub fn set_mode(&mut self, mode: ColourMode, range: ColourRange, queue: &Queue) {
+        self.mode = mode;
+        self.range = range;
+        self.uniforms.mode = mode.id();
+        match range {
+            ColourRange::Auto => self.uniforms.fixed_range = 0,
+            ColourRange::Fixed(min, max) => {
+                self.uniforms.fixed_range = 1;
+                self.uniforms.range = [min, max];
+            }
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
+    }
+
+    pub fn set_map(&mut self, map: ColourMap, device: &Device, queue: &Queue) {
+        self.bind_group = Self::create_bind_group(
+            &map,
+            &self.uniform_buffer,
+            &self.bind_group_layout,
+            device,
+            queue,
+        );
+        self.map = map;
+    }
+
+    /// Uploads the scalars used by [`ColourMode::Custom`], one per particle.
+    pub fn set_custom_scalars(&self, scalars: &[f32], queue: &Queue) -> Result<(), Error> {
+        if scalars.len() != self.uniforms.count as usize {
+            return Err(Error::ScalarCount {
+                particles: self.uniforms.count,
+                scalars: scalars.len(),
+            });
+        }
+        queue.write_buffer(&self.custom_buffer, 0, bytemuck::cast_slice(scalars));
+        Ok(())
+    }
+
+    pub fn compute(&self, encoder: &mut CommandEncoder, profiler: Option<&GpuProfiler>) {
+        if self.mode == ColourMode::Sprite {
+            return;
+        }
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Colour Compute"),
+            timestamp_writes: profiler.map(|p| p.compute_writes(GpuPass::Colour)),
+        });
+        pass.set_bind_group(0, &self.compute_bind_group, &[]);
+
+        let cells = self.uniforms.grid_width * self.uniforms.grid_height + 2;
+        let (x, y) = workgroups(cells);
+        pass.set_pipeline(&self.clear_pipeline);
+        pass.dispatch_workgroups(x, y, 1);
+
+        let (x, y) = workgroups(self.uniforms.count);
+        if self.mode == ColourMode::Density {
+            pass.set_pipeline(&self.splat_pipeline);
+            pass.dispatch_workgroups(x, y, 1);
+        }
+        pass.set_pipeline(&self.scalar_pipeline);
+        pass.dispatch_workgroups(x, y, 1);
+        pass.set_pipeline(&self.normalise_pipeline);
+        pass.dispatch_workgroups(x, y, 1);
+    }
+}
@@ -0,0 +1,185 @@
+struct Particle
+{
+    old_position : vec2<f32>,
+    position : vec2<f32>,
+    colour : vec4<f32>,
+    mass : f32,
+    radius : f32,
+    species : u32,
+    flags : u32,
+    age : f32,
+    lifetime : f32,
+}
+
+struct Uniforms
+{
+    mode : u32,
+    count : u32,
+    grid_width : u32,
+    grid_height : u32,
+    cell_size : f32,
+    timestep : f32,
+    fixed_range : u32,
+    range : vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<storage, read> particles : array<Particle>;
+
+@group(0) @binding(1)
+var<uniform> uniforms : Uniforms;
+
+@group(0) @binding(2)
+var<storage, read_write> scalars : array<f32>;
+
+@group(0) @binding(3)
+var<storage, read> custom : array<f32>;
+
+// Density cells, followed by the encoded minimum and maximum scalar of the frame
+@group(0) @binding(4)
+var<storage, read_write> density : array<atomic<u32>>;
+
+const SPEED : u32 = 1u;
+const KINETIC_ENERGY : u32 = 2u;
+const DENSITY : u32 = 3u;
+const SPECIES : u32 = 4u;
+const CUSTOM : u32 = 5u;
+
+fn thread_index(global_id : vec3<u32>, groups : vec3<u32>) -> u32
+{
+    return global_id.x + (global_id.y * groups.x * 64u);
+}
+
+fn cell(position : vec2<f32>) -> i32
+{
+    let cell = vec2<i32>(floor(position / uniforms.cell_size));
+    if cell.x < 0 || cell.y < 0 || cell.x >= i32(uniforms.grid_width) || cell.y >= i32(uniforms.grid_height)
+    {
+        return -1;
+    }
+    return cell.x + cell.y * i32(uniforms.grid_width);
+}
+
+// Maps a float onto a u32 whose unsigned ordering matches the float ordering
+fn encode(value : f32) -> u32
+{
+    let bits = bitcast<u32>(value);
+    if (bits & 0x80000000u) != 0u
+    {
+        return ~bits;
+    }
+    return bits | 0x80000000u;
+}
+
+fn decode(value : u32) -> f32
+{
+    if (value & 0x80000000u) != 0u
+    {
+        return bitcast<f32>(value & 0x7fffffffu);
+    }
+    return bitcast<f32>(~value);
+}
+
+@compute
+@workgroup_size(64)
+fn clear(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    let cells = uniforms.grid_width * uniforms.grid_height;
+    if index < cells
+    {
+        atomicStore(&density[index], 0u);
+    }
+    else if index == cells
+    {
+        atomicStore(&density[index], 0xffffffffu);
+    }
+    else if index == cells + 1u
+    {
+        atomicStore(&density[index], 0u);
+    }
+}
+
+@compute
+@workgroup_size(64)
+fn splat(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index >= uniforms.count
+    {
+        return;
+    }
+
+    let cell = cell(particles[index].position);
+    if cell >= 0
+    {
+        atomicAdd(&density[cell], 1u);
+    }
+}
+
+@compute
+@workgroup_size(64)
+fn scalar(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index >= uniforms.count
+    {
+        return;
+    }
+
+    let particle = particles[index];
+    let speed = length(particle.position - particle.old_position) / uniforms.timestep;
+
+    var value = 0.;
+    switch uniforms.mode
+    {
+        case SPEED: {
+            value = speed;
+        }
+        case KINETIC_ENERGY: {
+            value = 0.5 * particle.mass * speed * speed;
+        }
+        case DENSITY: {
+            let cell = cell(particle.position);
+            if cell >= 0
+            {
+                value = f32(atomicLoad(&density[cell]));
+            }
+        }
+        case SPECIES: {
+            value = f32(particle.species);
+        }
+        case CUSTOM: {
+            value = custom[index];
+        }
+        default: {}
+    }
+    scalars[index] = value;
+
+    if uniforms.fixed_range == 0u
+    {
+        let cells = uniforms.grid_width * uniforms.grid_height;
+        atomicMin(&density[cells], encode(value));
+        atomicMax(&density[cells + 1u], encode(value));
+    }
+}
+
+@compute
+@workgroup_size(64)
+fn normalise(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index >= uniforms.count
+    {
+        return;
+    }
+
+    var range = uniforms.range;
+    if uniforms.fixed_range == 0u
+    {
+        let cells = uniforms.grid_width * uniforms.grid_height;
+        range = vec2<f32>(decode(atomicLoad(&density[cells])), decode(atomicLoad(&density[cells + 1u])));
+    }
+
+    scalars[index] = clamp((scalars[index] - range.x) / max(range.y - range.x, 1e-6), 0., 1.);
+}
@@ -1,62 +1,214 @@
 use bytemuck::{Pod, Zeroable};
 use vecto_rs::linear::Vector;
 use wgpu::{
-    core::device::queue, include_wgsl, naga::front::wgsl, util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer, BufferSlice, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, PipelineCompilationOptions, Queue, RenderPassDescriptor
+    core::device::queue, include_wgsl, naga::front::wgsl, util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer, BufferDescriptor, BufferSlice, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, Maintain, PipelineCompilationOptions, Queue, RenderPassDescriptor, ShaderModule, ShaderModuleDescriptor, AddressMode, BindingResource, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Sampler, SamplerDescriptor, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor
 };
 use winit::window::Window;
 
-use crate::SIDE_LENGTH;
+use crate::{Error, SIDE_LENGTH};
 
-use super::ParticleInstance;
+use super::{
+    capture::MapResult,
+    field::VectorField,
+    forces::{self, ForceModule},
+    profiler::{GpuPass, GpuProfiler},
+    scene::{AdaptiveTimestep, Integrator, Scene},
+    shaders::catch_errors,
+    ParticleInstance,
+};
 
 #[repr(C)]
 #[derive(Clone, Copy, Zeroable, Pod)]
 struct Uniforms
 {
     length : u32,
-    _padding : u32,
+    count : u32,
     mouse_position : [f32; 2],
+    boundary_min : [f32; 2],
+    boundary_max : [f32; 2],
+    timestep : f32,
+    attractor_count : u32,
+    collider_count : u32,
+    boundary : u32,
+    restitution : f32,
+    adaptive : u32,
+    min_timestep : f32,
+    max_displacement : f32,
+    accuracy : f32,
+    field : u32,
+    field_min : [f32; 2],
+    field_max : [f32; 2],
 }
 
-pub struct ParticleCompute {
-    compute_pipeline: ComputePipeline,
+/// Must match `Clock` in `particle_compute.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Clock
+{
+    time : f32,
+    timestep : f32,
+    previous : f32,
+}
+
+/// Everything bound by `particle_compute.wgsl`, kept when the shader is swapped.
+struct Buffers
+{
+    particles : Buffer,
+    uniforms : Buffer,
+    attractors : Buffer,
+    colliders : Buffer,
+    /// Time and timestep, advanced on the GPU after every step.
+    clock : Buffer,
+    /// Largest speed and acceleration of the step, for adaptive timesteps.
+    limits : Buffer,
+    /// The vector field, a single zero texel while there is none.
+    field : Texture,
+    field_view : TextureView,
+    field_sampler : Sampler,
+}
+
+/// The pipelines of one version of `particle_compute.wgsl`.
+struct Pipelines
+{
+    physics : ComputePipeline,
+    physics_bind_group : BindGroup,
+    clock : ComputePipeline,
+    clock_bind_group : BindGroup,
+    /// The params of the force modules, when any are read.
+    forces_bind_group : Option<BindGroup>,
+}
 
-    particle_bind_group: BindGroup,
-    particle_buffer: Buffer,
+pub struct ParticleCompute {
+    buffers : Buffers,
+    pipelines : Pipelines,
+    integrator : Integrator,
+    /// The uniforms of the force modules with params, by module name.
+    force_params : Vec<(String, Buffer)>,
 
     uniforms : Uniforms,
-    uniform_buffer : Buffer,
+
+    clock_readback : Buffer,
+    /// The latest clock read back.
+    clock : Clock,
+    clock_copied : bool,
+    clock_in_flight : bool,
+    clock_mapped : MapResult,
+
+    particle_count : u32,
 }
 
-impl ParticleCompute {
-    pub fn new(device: &Device) -> Self {
-        let side_length = SIDE_LENGTH as usize;
-        let instance_count = side_length * side_length;
-        let mut instances = Vec::with_capacity(instance_count);
-
-        for i in 0..instance_count {
-            instances.push(ParticleInstance::new(
-                (i / side_length) as f32 + 0.5,
-                (i % side_length) as f32 + 0.5,
-            ));
-        }
+/// Threads per workgroup in `particle_compute.wgsl`.
+const WORKGROUP_SIZE : u32 = 64;
+
+/// The physics kernel before any force modules are composed in.
+pub(crate) const SHADER : &str = include_str!("particle_compute.wgsl");
 
+/// Simulated time covered by one step of the physics kernel.
+pub const TIMESTEP : f32 = 1. / 60.;
+
+/// Splits a dispatch of `count` threads over two dimensions, a single dimension is capped at 65535 workgroups.
+pub(crate) fn workgroups(count : u32) -> (u32, u32)
+{
+    let groups = count.div_ceil(WORKGROUP_SIZE).max(1);
+    let x = groups.min(u16::MAX as u32);
+    (x, groups.div_ceil(x))
+}
+
+impl ParticleCompute {
+    /// Uploads the particles of `scene` along with its forces, colliders and boundary.
+    /// Fails when the particles don't fit in a buffer the device can bind.
+    pub fn new(device: &Device, scene: &Scene) -> Result<Self, Error> {
+        let instances = scene.particles();
         let raw_instances = instances
             .iter()
             .map(ParticleInstance::raw)
             .collect::<Vec<_>>();
+
+        let required = std::mem::size_of_val(raw_instances.as_slice()) as u64;
+        let limits = device.limits();
+        for (limit, supported) in [
+            ("particle buffer size", limits.max_buffer_size),
+            ("storage buffer binding size", limits.max_storage_buffer_binding_size as u64),
+        ] {
+            if required > supported {
+                return Err(Error::LimitsExceeded { limit, required, supported });
+            }
+        }
         let particle_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Particle Instance Buffer"),
             contents: bytemuck::cast_slice(&raw_instances),
             usage: BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::STORAGE,
         });
 
-        let uniforms = Uniforms
+        let mut uniforms = Uniforms
         {
             length : SIDE_LENGTH as u32,
-            _padding : 0,
+            count : instances.len() as u32,
             mouse_position : [SIDE_LENGTH as f32 / 2., SIDE_LENGTH as f32 / 2.],
+            boundary_min : scene.boundary.min,
+            boundary_max : scene.boundary.max,
+            timestep : scene.simulation.timestep,
+            attractor_count : scene.attractors.len() as u32,
+            collider_count : scene.colliders.len() as u32,
+            boundary : scene.boundary.mode.id(),
+            restitution : scene.boundary.restitution,
+            adaptive : 0,
+            min_timestep : 0.,
+            max_displacement : 0.,
+            accuracy : 0.,
+            field : 0,
+            field_min : [0.; 2],
+            field_max : [1.; 2],
         };
+        Self::write_adaptive(&mut uniforms, scene.simulation.adaptive);
+
+        // Adaptive timesteps start small and grow, the limits are unknown before the first step
+        let timestep = match scene.simulation.adaptive
+        {
+            Some(adaptive) => adaptive.min.min(uniforms.timestep),
+            None => uniforms.timestep,
+        };
+        let clock = Clock { time : 0., timestep, previous : timestep };
+        let clock_buffer = device.create_buffer_init(&BufferInitDescriptor
+        {
+            label : Some("Clock Buffer"),
+            contents : bytemuck::cast_slice(&[clock]),
+            usage : BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        });
+        let limits_buffer = device.create_buffer_init(&BufferInitDescriptor
+        {
+            label : Some("Limits Buffer"),
+            contents : bytemuck::cast_slice(&[0u32; 2]),
+            usage : BufferUsages::STORAGE,
+        });
+        let clock_readback = device.create_buffer(&BufferDescriptor
+        {
+            label : Some("Clock Readback Buffer"),
+            size : std::mem::size_of::<Clock>() as u64,
+            usage : BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation : false,
+        });
+
+        // Storage buffers can't be empty, the counts in the uniforms keep the padding unread
+        let mut attractors = scene.attractors.iter().map(|a| a.raw()).collect::<Vec<_>>();
+        attractors.push(Zeroable::zeroed());
+        let attractor_buffer = device.create_buffer_init(&BufferInitDescriptor
+        {
+            label : Some("Attractor Buffer"),
+            contents : bytemuck::cast_slice(&attractors),
+            usage : BufferUsages::STORAGE,
+        });
+
+        let mut colliders = scene.colliders.iter().map(|c| c.raw()).collect::<Vec<_>>();
+        colliders.push(Zeroable::zeroed());
+        let collider_buffer = device.create_buffer_init(&BufferInitDescriptor
+        {
+            label : Some("Collider Buffer"),
+            contents : bytemuck::cast_slice(&colliders),
+            usage : BufferUsages::STORAGE,
+        });
+
+        let (field, field_view) = Self::create_field_texture(device, 1, 1);
 
         let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor
         {
@@ -65,64 +217,391 @@ impl ParticleCompute {
             usage : BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
-        let compute_shader = include_wgsl!("particle_compute.wgsl");
-        let compute_shader = device.create_shader_module(compute_shader);
-        let compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+        let buffers = Buffers
+        {
+            particles : particle_buffer,
+            uniforms : uniform_buffer,
+            attractors : attractor_buffer,
+            colliders : collider_buffer,
+            clock : clock_buffer,
+            limits : limits_buffer,
+            field,
+            field_view,
+            field_sampler : device.create_sampler(&SamplerDescriptor
+            {
+                label : Some("Vector Field Sampler"),
+                address_mode_u : AddressMode::ClampToEdge,
+                address_mode_v : AddressMode::ClampToEdge,
+                mag_filter : FilterMode::Linear,
+                min_filter : FilterMode::Linear,
+                ..Default::default()
+            }),
+        };
+        let compute_shader = device.create_shader_module(include_wgsl!("particle_compute.wgsl"));
+        let integrator = scene.simulation.integrator;
+        let pipelines = Self::create_pipelines(device, &compute_shader, integrator, &buffers, &[]);
+
+        Ok(Self {
+            buffers,
+            pipelines,
+            integrator,
+            force_params : Vec::new(),
+            uniforms,
+            clock_readback,
+            clock,
+            clock_copied : false,
+            clock_in_flight : false,
+            clock_mapped : MapResult::default(),
+            particle_count : instances.len() as u32,
+        })
+    }
+
+    fn create_pipelines(device : &Device, shader : &ShaderModule, integrator : Integrator, buffers : &Buffers, forces : &[BindGroupEntry]) -> Pipelines
+    {
+        let physics = device.create_compute_pipeline(&ComputePipelineDescriptor {
             label: Some("Particle Compute Pipeline"),
             layout: None,
-            module: &compute_shader,
-            entry_point: "main",
+            module: shader,
+            entry_point: integrator.entry_point(),
             compilation_options: PipelineCompilationOptions::default(),
             cache: None,
         });
-        let particle_bind_group = device.create_bind_group(&BindGroupDescriptor {
+        let clock = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("Clock Pipeline"),
+            layout: None,
+            module: shader,
+            entry_point: "advance_clock",
+            compilation_options: PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        // Auto layouts belong to their pipeline, so the bind groups are made with them
+        let clock_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Clock Bind Group"),
+            layout: &clock.get_bind_group_layout(0),
+            entries: &[BindGroupEntry {
+                binding: 1,
+                resource: buffers.uniforms.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: buffers.clock.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 5,
+                resource: buffers.limits.as_entire_binding(),
+            }],
+        });
+        let physics_bind_group = Self::create_physics_bind_group(device, &physics, buffers);
+        let forces_bind_group = (!forces.is_empty()).then(|| device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Force Module Bind Group"),
+            layout: &physics.get_bind_group_layout(1),
+            entries: forces,
+        }));
+
+        Pipelines { physics, physics_bind_group, clock, clock_bind_group, forces_bind_group }
+    }
+
+    fn create_physics_bind_group(device : &Device, physics : &ComputePipeline, buffers : &Buffers) -> BindGroup
+    {
+        device.create_bind_group(&BindGroupDescriptor {
             label: Some("Particle Buffer"),
-            layout: &compute_pipeline.get_bind_group_layout(0),
+            layout: &physics.get_bind_group_layout(0),
             entries: &[BindGroupEntry {
                 binding: 0,
-                resource: particle_buffer.as_entire_binding(),
+                resource: buffers.particles.as_entire_binding(),
             },
             BindGroupEntry {
                 binding: 1,
-                resource: uniform_buffer.as_entire_binding(),
+                resource: buffers.uniforms.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: buffers.attractors.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: buffers.colliders.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: buffers.clock.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 5,
+                resource: buffers.limits.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 6,
+                resource: BindingResource::TextureView(&buffers.field_view),
+            },
+            BindGroupEntry {
+                binding: 7,
+                resource: BindingResource::Sampler(&buffers.field_sampler),
             }],
+        })
+    }
+
+    fn create_field_texture(device : &Device, width : u32, height : u32) -> (Texture, TextureView)
+    {
+        let texture = device.create_texture(&TextureDescriptor
+        {
+            label : Some("Vector Field"),
+            size : Extent3d { width, height, depth_or_array_layers : 1 },
+            mip_level_count : 1,
+            sample_count : 1,
+            dimension : TextureDimension::D2,
+            format : TextureFormat::Rg16Float,
+            usage : TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats : &[],
         });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    /// Uploads `field` for the kernel to sample, `None` removes it. Fields of the same size
+    /// as the last one reuse its texture, so a new one can be uploaded every frame.
+    pub fn set_field(&mut self, field : Option<&VectorField>, device : &Device, queue : &Queue)
+    {
+        let Some(field) = field else
+        {
+            self.uniforms.field = 0;
+            queue.write_buffer(&self.buffers.uniforms, 0, bytemuck::cast_slice(&[self.uniforms]));
+            return;
+        };
 
-        Self {
-            particle_buffer,
-            compute_pipeline,
-            particle_bind_group,
-            uniform_buffer,
-            uniforms
+        let (width, height) = field.size();
+        if (self.buffers.field.width(), self.buffers.field.height()) != (width, height)
+        {
+            let (texture, view) = Self::create_field_texture(device, width, height);
+            self.buffers.field = texture;
+            self.buffers.field_view = view;
+            self.pipelines.physics_bind_group = Self::create_physics_bind_group(device, &self.pipelines.physics, &self.buffers);
+        }
+
+        queue.write_texture(
+            ImageCopyTexture
+            {
+                texture : &self.buffers.field,
+                mip_level : 0,
+                origin : Origin3d::ZERO,
+                aspect : TextureAspect::All,
+            },
+            bytemuck::cast_slice(&field.texels()),
+            ImageDataLayout
+            {
+                offset : 0,
+                bytes_per_row : Some(width * 4),
+                rows_per_image : Some(height),
+            },
+            Extent3d { width, height, depth_or_array_layers : 1 },
+        );
+
+        self.uniforms.field = 1;
+        self.uniforms.field_min = field.min;
+        self.uniforms.field_max = field.max;
+        queue.write_buffer(&self.buffers.uniforms, 0, bytemuck::cast_slice(&[self.uniforms]));
+    }
+
+    /// Swaps in a new version of `particle_compute.wgsl` with `modules` composed in, keeping
+    /// the particles and clock. On error the current shader stays in use.
+    pub fn set_shader(&mut self, source : &str, modules : &[ForceModule], device : &Device) -> Result<(), String>
+    {
+        let composed = forces::compose(source, modules)?;
+
+        let force_params : Vec<_> = composed.bound.iter().map(|&i| {
+            let module = &modules[i];
+            let buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("Force Module Params"),
+                contents: &module.padded_params().unwrap_or_default(),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            });
+            (i as u32, module.name().to_string(), buffer)
+        }).collect();
+        let entries : Vec<_> = force_params.iter().map(|(binding, _, buffer)| BindGroupEntry {
+            binding: *binding,
+            resource: buffer.as_entire_binding(),
+        }).collect();
+
+        let pipelines = catch_errors(device, || {
+            let shader = device.create_shader_module(ShaderModuleDescriptor {
+                label: Some("Particle Compute Shader"),
+                source: wgpu::ShaderSource::Wgsl(composed.source.into()),
+            });
+            Self::create_pipelines(device, &shader, self.integrator, &self.buffers, &entries)
+        })?;
+        self.pipelines = pipelines;
+        self.force_params = force_params.into_iter().map(|(_, name, buffer)| (name, buffer)).collect();
+        Ok(())
+    }
+
+    /// Updates the params of the force module called `name`, returns false when it has none bound.
+    pub fn write_force_params(&self, name : &str, params : &[u8], queue : &Queue) -> bool
+    {
+        match self.force_params.iter().find(|(n, _)| n == name)
+        {
+            Some((_, buffer)) => {
+                queue.write_buffer(buffer, 0, params);
+                true
+            }
+            None => false,
         }
     }
 
     pub fn particle_count(&self) -> u32
     {
-        (SIDE_LENGTH * SIDE_LENGTH) as u32
+        self.particle_count
+    }
+
+    pub fn particle_buffer(&self) -> &Buffer
+    {
+        &self.buffers.particles
+    }
+
+    /// The scene's attractors followed by one unused zeroed attractor.
+    pub fn attractor_buffer(&self) -> &Buffer
+    {
+        &self.buffers.attractors
+    }
+
+    pub fn attractor_count(&self) -> u32
+    {
+        self.uniforms.attractor_count
     }
 
     pub fn get_particle_buffer(&self) -> BufferSlice
     {
-        self.particle_buffer.slice(..)
+        self.buffers.particles.slice(..)
     }
 
     pub fn mouse(&mut self, mouse : Vector, queue : &Queue)
     {
         self.uniforms.mouse_position = [mouse.x, mouse.y];
-        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
+        queue.write_buffer(&self.buffers.uniforms, 0, bytemuck::cast_slice(&[self.uniforms]));
+    }
+
+    /// Lets the GPU pick each timestep, up to the fixed one. `None` goes back to the fixed timestep.
+    pub fn set_adaptive(&mut self, adaptive : Option<AdaptiveTimestep>, queue : &Queue)
+    {
+        Self::write_adaptive(&mut self.uniforms, adaptive);
+        queue.write_buffer(&self.buffers.uniforms, 0, bytemuck::cast_slice(&[self.uniforms]));
     }
 
-    pub fn compute(&self, encoder: &mut CommandEncoder) {
+    fn write_adaptive(uniforms : &mut Uniforms, adaptive : Option<AdaptiveTimestep>)
+    {
+        let adaptive = match adaptive
+        {
+            Some(adaptive) => adaptive,
+            None =>
+            {
+                uniforms.adaptive = 0;
+                return;
+            }
+        };
+        uniforms.adaptive = 1;
+        uniforms.min_timestep = adaptive.min.min(uniforms.timestep);
+        uniforms.max_displacement = adaptive.max_displacement;
+        uniforms.accuracy = adaptive.accuracy;
+    }
+
+    /// Reads back the clock copied by an earlier step, if it has arrived. The time and
+    /// timestep live on the GPU, their getters return the latest read back.
+    pub fn poll(&mut self, device : &Device)
+    {
+        if !self.clock_in_flight
+        {
+            return;
+        }
+
+        device.poll(Maintain::Poll);
+        match self.clock_mapped.take(&self.clock_readback, device, "clock")
+        {
+            None => return,
+            // Keeps the last clock read back until the next copy arrives
+            Some(false) =>
+            {
+                self.clock_in_flight = false;
+                return;
+            }
+            Some(true) => {}
+        }
+
+        self.clock = {
+            let view = self.clock_readback.slice(..).get_mapped_range();
+            bytemuck::pod_read_unaligned(&view)
+        };
+        self.clock_readback.unmap();
+        self.clock_in_flight = false;
+    }
+
+    /// Starts reading back the clock copied this frame, call after submitting.
+    pub fn map(&mut self)
+    {
+        if !std::mem::replace(&mut self.clock_copied, false)
+        {
+            return;
+        }
+
+        self.clock_mapped.map(&self.clock_readback);
+        self.clock_in_flight = true;
+    }
+
+    /// Simulated seconds since the scene was loaded.
+    pub fn time(&self) -> f32
+    {
+        self.clock.time
+    }
+
+    /// Length of the last step taken, in seconds.
+    pub fn last_timestep(&self) -> f32
+    {
+        self.clock.previous
+    }
+
+    /// The fixed timestep, or the largest one when adaptive. Velocities are stored as the
+    /// displacement over this long.
+    pub fn timestep(&self) -> f32
+    {
+        self.uniforms.timestep
+    }
+
+    /// The buffer holding the simulated time, see `Clock` in `particle_compute.wgsl`.
+    pub fn clock_buffer(&self) -> &Buffer
+    {
+        &self.buffers.clock
+    }
+
+    pub fn compute(&self, encoder: &mut CommandEncoder, profiler: Option<&GpuProfiler>) {
         let mut particle_compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
             label: Some("Particle Compute"),
-            timestamp_writes: None,
+            timestamp_writes: profiler.map(|p| p.compute_writes(GpuPass::Simulate)),
         });
 
         
 
-        particle_compute_pass.set_pipeline(&self.compute_pipeline);
-        particle_compute_pass.set_bind_group(0, &self.particle_bind_group, &[]);
-        particle_compute_pass.dispatch_workgroups(SIDE_LENGTH as u32, SIDE_LENGTH as u32, 1);
+        particle_compute_pass.set_pipeline(&self.pipelines.physics);
+        particle_compute_pass.set_bind_group(0, &self.pipelines.physics_bind_group, &[]);
+        if let Some(forces) = &self.pipelines.forces_bind_group
+        {
+            particle_compute_pass.set_bind_group(1, forces, &[]);
+        }
+        let (x, y) = workgroups(self.particle_count);
+        particle_compute_pass.dispatch_workgroups(x, y, 1);
+
+        particle_compute_pass.set_pipeline(&self.pipelines.clock);
+        particle_compute_pass.set_bind_group(0, &self.pipelines.clock_bind_group, &[]);
+        particle_compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    /// Copies the clock out for reading after the step, unless the last copy is still being read.
+    pub fn copy_clock(&mut self, encoder : &mut CommandEncoder)
+    {
+        if self.clock_in_flight
+        {
+            return;
+        }
+
+        encoder.copy_buffer_to_buffer(&self.buffers.clock, 0, &self.clock_readback, 0, self.clock_readback.size());
+        self.clock_copied = true;
     }
 }
@@ -0,0 +1,338 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer, BufferDescriptor, BufferUsages,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    Maintain, PipelineCompilationOptions,
+};
+
+use super::{
+    capture::MapResult,
+    compute::{workgroups, ParticleCompute},
+};
+
+/// Threads per workgroup in `diagnostics.wgsl`, each workgroup sums this many inputs.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Results that can be waiting to be read back at once, steps past this are skipped.
+const READBACK_SLOTS: usize = 4;
+
+/// Aggregate quantities of the whole simulation at one step.
+///
+/// Velocities are those stored by the integrator, and the potential energy is that of
+/// the attractors alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    /// The step the quantities were measured after.
+    pub step: u64,
+    pub kinetic_energy: f32,
+    pub potential_energy: f32,
+    pub momentum: [f32; 2],
+    /// Around the first attractor, or the origin without attractors.
+    pub angular_momentum: f32,
+    pub centre_of_mass: [f32; 2],
+    pub mass: f32,
+    pub bounds_min: [f32; 2],
+    pub bounds_max: [f32; 2],
+}
+
+impl Diagnostics {
+    pub fn total_energy(&self) -> f32 {
+        self.kinetic_energy + self.potential_energy
+    }
+}
+
+/// Must match `Partial` in `diagnostics.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Partial {
+    min: [f32; 2],
+    max: [f32; 2],
+    momentum: [f32; 2],
+    moment: [f32; 2],
+    mass: f32,
+    kinetic: f32,
+    potential: f32,
+    angular: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Uniforms {
+    timestep: f32,
+    attractor_count: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Level {
+    input_offset: u32,
+    input_count: u32,
+    output_offset: u32,
+    _padding: u32,
+}
+
+struct Slot {
+    buffer: Buffer,
+    /// The step being read back, `None` while the slot is free.
+    step: Option<u64>,
+    mapped: MapResult,
+}
+
+/// Sums energy, momentum and mass over every particle with a tree reduction on the GPU.
+///
+/// Each level of the reduction has every workgroup add up its inputs into one partial
+/// sum, until a single one is left. Results are read back without stalling and arrive
+/// a frame or two after their step, in order.
+pub struct Reduction {
+    partial_buffer: Buffer,
+    /// The bind group and input count of each level, the first reads the particles.
+    levels: Vec<(BindGroup, u32)>,
+    /// Where the final sum ends up in the partial buffer.
+    result_offset: u64,
+
+    particle_pipeline: ComputePipeline,
+    partial_pipeline: ComputePipeline,
+
+    slots: Vec<Slot>,
+    /// The slot this frame's result was copied into, waiting to be mapped.
+    copied: Option<usize>,
+}
+
+impl Reduction {
+    /// Reduces the particles of `compute`, along with the potential of its attractors.
+    pub fn new(compute: &ParticleCompute, device: &Device) -> Self {
+        let particles = compute.particle_buffer();
+        let count = compute.particle_count();
+        let uniforms = Uniforms {
+            timestep: compute.timestep(),
+            attractor_count: compute.attractor_count(),
+        };
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Diagnostics Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniforms]),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        // Each level writes one partial per workgroup after the previous level's output
+        let mut level_data = Vec::new();
+        let mut input_offset = 0;
+        let mut input_count = count.max(1);
+        let mut output_offset = 0;
+        loop {
+            let outputs = input_count.div_ceil(WORKGROUP_SIZE);
+            level_data.push(Level {
+                input_offset,
+                input_count,
+                output_offset,
+                _padding: 0,
+            });
+            input_offset = output_offset;
+            output_offset += outputs;
+            input_count = outputs;
+            if outputs == 1 {
+                break;
+            }
+        }
+        let partial_size = std::mem::size_of::<Partial>() as u64;
+
+        let partial_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Diagnostics Partial Buffer"),
+            size: output_offset as u64 * partial_size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("diagnostics.wgsl"));
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                module: &shader,
+                entry_point,
+                compilation_options: PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+        let particle_pipeline = pipeline("reduce_particles");
+        let partial_pipeline = pipeline("reduce_partials");
+
+        let levels = level_data
+            .iter()
+            .enumerate()
+            .map(|(i, level)| {
+                let level_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("Diagnostics Level Buffer"),
+                    contents: bytemuck::cast_slice(&[*level]),
+                    usage: BufferUsages::UNIFORM,
+                });
+
+                // Auto layouts only hold the bindings their entry point uses
+                let (layout, entries) = match i {
+                    0 => (
+                        particle_pipeline.get_bind_group_layout(0),
+                        vec![
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: uniform_buffer.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: level_buffer.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 2,
+                                resource: particles.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 3,
+                                resource: compute.attractor_buffer().as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 4,
+                                resource: partial_buffer.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 5,
+                                resource: compute.clock_buffer().as_entire_binding(),
+                            },
+                        ],
+                    ),
+                    _ => (
+                        partial_pipeline.get_bind_group_layout(0),
+                        vec![
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: level_buffer.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 4,
+                                resource: partial_buffer.as_entire_binding(),
+                            },
+                        ],
+                    ),
+                };
+
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Diagnostics Bind Group"),
+                    layout: &layout,
+                    entries: &entries,
+                });
+                (bind_group, level.input_count)
+            })
+            .collect();
+
+        let slots = (0..READBACK_SLOTS)
+            .map(|_| Slot {
+                buffer: device.create_buffer(&BufferDescriptor {
+                    label: Some("Diagnostics Readback Buffer"),
+                    size: partial_size,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                step: None,
+                mapped: MapResult::default(),
+            })
+            .collect();
+
+        Self {
+            partial_buffer,
+            levels,
+            result_offset: (output_offset as u64 - 1) * partial_size,
+            particle_pipeline,
+            partial_pipeline,
+            slots,
+            copied: None,
+        }
+    }
+
+    /// Records the reduction of the particles after `step`, when a readback slot is free.
+    pub fn compute(&mut self, encoder: &mut CommandEncoder, step: u64) {
+        let Some(slot) = self.slots.iter().position(|slot| slot.step.is_none()) else {
+            return;
+        };
+
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Diagnostics Reduction"),
+                timestamp_writes: None,
+            });
+            for (i, (bind_group, input_count)) in self.levels.iter().enumerate() {
+                pass.set_pipeline(match i {
+                    0 => &self.particle_pipeline,
+                    _ => &self.partial_pipeline,
+                });
+                pass.set_bind_group(0, bind_group, &[]);
+                let (x, y) = workgroups(*input_count);
+                pass.dispatch_workgroups(x, y, 1);
+            }
+        }
+
+        let slot_buffer = &self.slots[slot].buffer;
+        encoder.copy_buffer_to_buffer(
+            &self.partial_buffer,
+            self.result_offset,
+            slot_buffer,
+            0,
+            slot_buffer.size(),
+        );
+        self.slots[slot].step = Some(step);
+        self.copied = Some(slot);
+    }
+
+    /// Starts reading back the result recorded this frame, call after submitting.
+    pub fn map(&mut self) {
+        let Some(slot) = self.copied.take() else {
+            return;
+        };
+
+        let slot = &self.slots[slot];
+        slot.mapped.map(&slot.buffer);
+    }
+
+    /// Every result read back since the last call, oldest first.
+    pub fn poll(&mut self, device: &Device) -> Vec<Diagnostics> {
+        if self.slots.iter().all(|slot| slot.step.is_none()) {
+            return Vec::new();
+        }
+        device.poll(Maintain::Poll);
+
+        let mut results = Vec::new();
+        for slot in &mut self.slots {
+            let Some(step) = slot.step else {
+                continue;
+            };
+            match slot.mapped.take(&slot.buffer, device, "diagnostics") {
+                None => continue,
+                // The step is lost, free the slot for the next one
+                Some(false) => {
+                    slot.step = None;
+                    continue;
+                }
+                Some(true) => {}
+            }
+
+            let partial: Partial = {
+                let view = slot.buffer.slice(..).get_mapped_range();
+                bytemuck::pod_read_unaligned(&view)
+            };
+            slot.buffer.unmap();
+            slot.step = None;
+
+            let mass = partial.mass.max(f32::MIN_POSITIVE);
+            results.push(Diagnostics {
+                step,
+                kinetic_energy: partial.kinetic,
+                potential_energy: partial.potential,
+                momentum: partial.momentum,
+                angular_momentum: partial.angular,
+                centre_of_mass: [partial.moment[0] / mass, partial.moment[1] / mass],
+                mass: partial.mass,
+                bounds_min: partial.min,
+                bounds_max: partial.max,
+            });
+        }
+        results.sort_by_key(|d| d.step);
+        results
+    }
+}
@@ -0,0 +1,221 @@
+struct Particle
+{
+    old_position : vec2<f32>,
+    position : vec2<f32>,
+    colour : vec4<f32>,
+    mass : f32,
+    radius : f32,
+    species : u32,
+    flags : u32,
+    age : f32,
+    lifetime : f32,
+}
+
+struct Attractor
+{
+    position : vec2<f32>,
+    velocity : vec2<f32>,
+    strength : f32,
+    falloff : f32,
+    softening : f32,
+}
+
+// Sums over a range of particles, the bounds are a minimum and maximum instead
+struct Partial
+{
+    min : vec2<f32>,
+    max : vec2<f32>,
+    momentum : vec2<f32>,
+    // Mass weighted position, divided by the mass for the centre of mass
+    moment : vec2<f32>,
+    mass : f32,
+    kinetic : f32,
+    potential : f32,
+    angular : f32,
+}
+
+struct Uniforms
+{
+    // Velocities are stored as the displacement over this many seconds
+    timestep : f32,
+    attractor_count : u32,
+}
+
+struct Clock
+{
+    time : f32,
+    timestep : f32,
+    previous : f32,
+}
+
+struct Level
+{
+    input_offset : u32,
+    input_count : u32,
+    output_offset : u32,
+}
+
+@group(0) @binding(0)
+var<uniform> uniforms : Uniforms;
+
+@group(0) @binding(1)
+var<uniform> level : Level;
+
+@group(0) @binding(2)
+var<storage, read> particles : array<Particle>;
+
+@group(0) @binding(3)
+var<storage, read> attractors : array<Attractor>;
+
+@group(0) @binding(4)
+var<storage, read_write> partials : array<Partial>;
+
+@group(0) @binding(5)
+var<storage, read> clock : Clock;
+
+const WORKGROUP_SIZE : u32 = 64u;
+const LARGEST : f32 = 3.4e38;
+
+var<workgroup> shared_partials : array<Partial, WORKGROUP_SIZE>;
+
+fn empty() -> Partial
+{
+    var p : Partial;
+    p.min = vec2<f32>(LARGEST);
+    p.max = vec2<f32>(-LARGEST);
+    return p;
+}
+
+fn combine(a : Partial, b : Partial) -> Partial
+{
+    var p : Partial;
+    p.min = min(a.min, b.min);
+    p.max = max(a.max, b.max);
+    p.momentum = a.momentum + b.momentum;
+    p.moment = a.moment + b.moment;
+    p.mass = a.mass + b.mass;
+    p.kinetic = a.kinetic + b.kinetic;
+    p.potential = a.potential + b.potential;
+    p.angular = a.angular + b.angular;
+    return p;
+}
+
+// The potential whose gradient is the attractor force in `particle_compute.wgsl`
+fn potential(position : vec2<f32>) -> f32
+{
+    var total = 0.;
+    for (var i = 0u; i < uniforms.attractor_count; i++)
+    {
+        let attractor = attractors[i];
+        let offset = attractor.position + attractor.velocity * clock.time - position;
+        let dist = sqrt(dot(offset, offset) + attractor.softening * attractor.softening);
+        if dist <= 0.
+        {
+            continue;
+        }
+
+        if abs(attractor.falloff - 1.) < 1e-6
+        {
+            total += attractor.strength * log(dist);
+        }
+        else
+        {
+            let power = 1. - attractor.falloff;
+            total += attractor.strength * pow(dist, power) / power;
+        }
+    }
+    return total;
+}
+
+// Angular momentum is taken around the first attractor
+fn origin() -> vec2<f32>
+{
+    if uniforms.attractor_count == 0u
+    {
+        return vec2<f32>(0.);
+    }
+    return attractors[0].position + attractors[0].velocity * clock.time;
+}
+
+fn particle_partial(index : u32) -> Partial
+{
+    let particle = particles[index];
+    let velocity = (particle.position - particle.old_position) / uniforms.timestep;
+    let momentum = particle.mass * velocity;
+    let arm = particle.position - origin();
+
+    var p : Partial;
+    p.min = particle.position;
+    p.max = particle.position;
+    p.momentum = momentum;
+    p.moment = particle.mass * particle.position;
+    p.mass = particle.mass;
+    p.kinetic = 0.5 * dot(momentum, velocity);
+    p.potential = potential(particle.position);
+    p.angular = arm.x * momentum.y - arm.y * momentum.x;
+    return p;
+}
+
+// Tree reduces the workgroup's partials and writes the total for the workgroup
+fn reduce_workgroup(value : Partial, local : u32, workgroup : u32)
+{
+    shared_partials[local] = value;
+    workgroupBarrier();
+
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u)
+    {
+        if local < stride
+        {
+            shared_partials[local] = combine(shared_partials[local], shared_partials[local + stride]);
+        }
+        workgroupBarrier();
+    }
+
+    if local == 0u
+    {
+        partials[level.output_offset + workgroup] = shared_partials[0];
+    }
+}
+
+fn workgroup_index(workgroup_id : vec3<u32>, groups : vec3<u32>) -> u32
+{
+    return workgroup_id.x + workgroup_id.y * groups.x;
+}
+
+@compute
+@workgroup_size(64)
+fn reduce_particles(
+    @builtin(local_invocation_index) local : u32,
+    @builtin(workgroup_id) workgroup_id : vec3<u32>,
+    @builtin(num_workgroups) groups : vec3<u32>,
+)
+{
+    let workgroup = workgroup_index(workgroup_id, groups);
+    let index = workgroup * WORKGROUP_SIZE + local;
+
+    var value = empty();
+    if index < level.input_count
+    {
+        value = particle_partial(index);
+    }
+    reduce_workgroup(value, local, workgroup);
+}
+
+@compute
+@workgroup_size(64)
+fn reduce_partials(
+    @builtin(local_invocation_index) local : u32,
+    @builtin(workgroup_id) workgroup_id : vec3<u32>,
+    @builtin(num_workgroups) groups : vec3<u32>,
+)
+{
+    let workgroup = workgroup_index(workgroup_id, groups);
+    let index = workgroup * WORKGROUP_SIZE + local;
+
+    var value = empty();
+    if index < level.input_count
+    {
+        value = partials[level.input_offset + index];
+    }
+    reduce_workgroup(value, local, workgroup);
+}
@@ -0,0 +1,178 @@
+use std::path::Path;
+
+use image::DynamicImage;
+
+use crate::Error;
+
+/// A grid of forces covering a rectangle of the world, sampled bilinearly by the physics kernel.
+///
+/// Values are stored row by row starting from the `min` corner, so the first row lies along
+/// the bottom of the rectangle. Outside the rectangle the field is zero. On the GPU the
+/// field is kept as half floats, which filter on every adapter.
+#[derive(Clone, Debug)]
+pub struct VectorField {
+    width: u32,
+    height: u32,
+    data: Vec<[f32; 2]>,
+    /// The lower corner of the rectangle covered.
+    pub min: [f32; 2],
+    /// The upper corner of the rectangle covered.
+    pub max: [f32; 2],
+}
+
+impl VectorField {
+    /// A `width` by `height` field, covering one world unit per value until given bounds.
+    /// Fails unless the field is at least 1x1 and `data` has a value for each point.
+    pub fn new(width: u32, height: u32, data: Vec<[f32; 2]>) -> Result<Self, Error> {
+        if width == 0 || height == 0 || data.len() != width as usize * height as usize {
+            return Err(Error::FieldSize {
+                width,
+                height,
+                values: data.len(),
+            });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            data,
+            min: [0., 0.],
+            max: [width as f32, height as f32],
+        })
+    }
+
+    /// Reads the force from the red and green channels of an image, mapping each from
+    /// black to white onto `range`. The top of the image is the top of the field.
+    pub fn from_image(image: &DynamicImage, range: [f32; 2]) -> Result<Self, Error> {
+        let image = image.to_rgb32f();
+        let (width, height) = image.dimensions();
+        let map = |c: f32| range[0] + (range[1] - range[0]) * c;
+
+        let data = image
+            .rows()
+            .rev()
+            .flatten()
+            .map(|pixel| [map(pixel[0]), map(pixel[1])])
+            .collect();
+        Self::new(width, height, data)
+    }
+
+    pub fn load(path: impl AsRef<Path>, range: [f32; 2]) -> Result<Self, Error> {
+        Self::from_image(&image::open(path)?, range)
+    }
+
+    pub fn with_bounds(mut self, min: [f32; 2], max: [f32; 2]) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn data(&self) -> &[[f32; 2]] {
+        &self.data
+    }
+
+    /// For updating the field in place before uploading it again.
+    pub fn data_mut(&mut self) -> &mut [[f32; 2]] {
+        &mut self.data
+    }
+
+    /// The values as `Rg16Float` texels.
+    pub(crate) fn texels(&self) -> Vec<[u16; 2]> {
+        self.data
+            .iter()
+            .map(|&[x, y]| [half_float(x), half_float(y)])
+            .collect()
+    }
+}
+
+/// Rounds to the nearest half float with ties to even, values beyond its range become
+/// infinite.
+fn half_float(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    if exponent >= 31 {
+        return sign | 0x7c00;
+    }
+
+    let (half, dropped) = if exponent <= 0 {
+        // Subnormal, the implicit leading bit becomes part of the mantissa
+        if exponent < -10 {
+            return sign;
+        }
+        (mantissa | 0x80_0000, (14 - exponent) as u32)
+    } else {
+        ((exponent as u32) << 23 | mantissa, 13)
+    };
+
+    // A carry out of the mantissa rounds up into the exponent, and on into infinity
+    let rest = half & ((1 << dropped) - 1);
+    let halfway = 1 << (dropped - 1);
+    let half = half >> dropped;
+    let round_up = rest > halfway || (rest == halfway && half & 1 == 1);
+    sign | (half + round_up as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_float_exact_values() {
+        assert_eq!(half_float(0.), 0x0000);
+        assert_eq!(half_float(-0.), 0x8000);
+        assert_eq!(half_float(1.), 0x3c00);
+        assert_eq!(half_float(-2.), 0xc000);
+        assert_eq!(half_float(65504.), 0x7bff);
+        assert_eq!(half_float(-65504.), 0xfbff);
+        // The smallest normal, and the smallest and largest subnormals
+        assert_eq!(half_float(2f32.powi(-14)), 0x0400);
+        assert_eq!(half_float(2f32.powi(-24)), 0x0001);
+        assert_eq!(half_float(2f32.powi(-14) - 2f32.powi(-24)), 0x03ff);
+    }
+
+    #[test]
+    fn half_float_rounds_to_nearest_even() {
+        let ulp = 2f32.powi(-10);
+        assert_eq!(half_float(1. + ulp / 2.), 0x3c00);
+        assert_eq!(half_float(1. + ulp * 1.5), 0x3c02);
+        assert_eq!(half_float(1. + ulp * 0.75), 0x3c01);
+        // Subnormal ties, halfway to the smallest subnormal is zero
+        assert_eq!(half_float(2f32.powi(-25)), 0x0000);
+        assert_eq!(half_float(2f32.powi(-25) * 1.5), 0x0001);
+        assert_eq!(half_float(2f32.powi(-24) * 1.5), 0x0002);
+        assert_eq!(half_float(2f32.powi(-40)), 0x0000);
+        // Rounding up out of the subnormals and out of the largest half
+        assert_eq!(half_float(2f32.powi(-14) - 2f32.powi(-26)), 0x0400);
+        assert_eq!(half_float(65519.), 0x7bff);
+        assert_eq!(half_float(65520.), 0x7c00);
+    }
+
+    #[test]
+    fn half_float_special_values() {
+        assert_eq!(half_float(f32::INFINITY), 0x7c00);
+        assert_eq!(half_float(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(half_float(1e6), 0x7c00);
+        assert_eq!(half_float(-f32::MAX), 0xfc00);
+        let nan = half_float(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x03ff, 0);
+    }
+
+    #[test]
+    fn field_size_is_checked() {
+        assert!(VectorField::new(2, 2, vec![[0., 0.]; 4]).is_ok());
+        assert!(VectorField::new(2, 2, vec![[0., 0.]; 3]).is_err());
+        assert!(VectorField::new(0, 0, Vec::new()).is_err());
+        assert!(VectorField::from_image(&DynamicImage::new_rgb8(0, 0), [-1., 1.]).is_err());
+    }
+}
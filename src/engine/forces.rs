@@ -0,0 +1,194 @@
+use bytemuck::Pod;
+
+use super::shaders::{self, ShaderFile};
+
+/// Lines around the part of `particle_compute.wgsl` the registered modules replace.
+const BEGIN: &str = "// force modules begin";
+const END: &str = "// force modules end";
+
+/// A custom force written in WGSL, composed into the physics kernel alongside the attractors.
+///
+/// The source defines `fn force(p : Particle, idx : u32) -> vec2<f32>`, returning the force
+/// on particle `idx`. `p` is the particle at the position being integrated, which within a
+/// step of the higher order integrators is not the stored one. Anything declared by
+/// `particle_compute.wgsl` can be used, like `clock.time` or the `uniforms`.
+///
+/// A module given params also declares `struct Params`, matching the layout of the Rust
+/// type, and reads them through `params`. `force`, `Params` and `params` are renamed per
+/// module so any number of modules can be registered at once, other declarations must
+/// have names of their own.
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Pod, Zeroable)]
+/// struct Drag { coefficient: f32, _padding: [f32; 3] }
+///
+/// let drag = ForceModule::new("drag", r#"
+///     struct Params { coefficient : f32 }
+///
+///     fn force(p : Particle, idx : u32) -> vec2<f32>
+///     {
+///         return -params.coefficient * velocity(idx);
+///     }
+/// "#)
+/// .with_params(&Drag { coefficient: 0.1, _padding: [0.; 3] });
+/// instance.add_force_module(drag)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct ForceModule {
+    name: String,
+    source: String,
+    params: Option<Vec<u8>>,
+}
+
+impl ForceModule {
+    /// `name` identifies the module and must be a WGSL identifier of lowercase letters,
+    /// digits and underscores.
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            source: source.into(),
+            params: None,
+        }
+    }
+
+    /// Binds `params` as the module's uniforms, padded to a multiple of 16 bytes.
+    pub fn with_params<T: Pod>(mut self, params: &T) -> Self {
+        self.set_params(params);
+        self
+    }
+
+    pub fn set_params<T: Pod>(&mut self, params: &T) {
+        self.params = Some(bytemuck::bytes_of(params).to_vec());
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The params as uploaded, padded to a multiple of 16 bytes.
+    pub(crate) fn padded_params(&self) -> Option<Vec<u8>> {
+        self.params.as_ref().map(|params| {
+            let mut padded = params.clone();
+            padded.resize(params.len().next_multiple_of(16).max(16), 0);
+            padded
+        })
+    }
+
+    fn prefixed(&self, identifier: &str) -> String {
+        format!("module_{}_{}", self.name, identifier)
+    }
+}
+
+/// The physics kernel with force modules composed in.
+pub(crate) struct Composed {
+    pub source: String,
+    /// The modules whose params the kernel reads, each is bound at its index in the list.
+    /// Params that go unread are left out, as the pipeline layout has no place for them.
+    pub bound: Vec<usize>,
+}
+
+/// Replaces the default forces of `base` with calls to `modules`, and validates the result.
+pub(crate) fn compose(base: &str, modules: &[ForceModule]) -> Result<Composed, String> {
+    let (Some(begin), Some(end)) = (base.find(BEGIN), base.find(END)) else {
+        return Err(format!(
+            "{} is missing the `{}` and `{}` lines",
+            ShaderFile::ParticleCompute.file_name(),
+            BEGIN,
+            END
+        ));
+    };
+
+    let mut source = base[..begin].to_string();
+    for (i, module) in modules.iter().enumerate() {
+        let valid_name = module.name.starts_with(|c: char| c.is_ascii_lowercase())
+            && module
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "force module names must be lowercase letters, digits and underscores, not `{}`",
+                module.name
+            ));
+        }
+        if modules[..i].iter().any(|m| m.name == module.name) {
+            return Err(format!("there are two force modules named `{}`", module.name));
+        }
+
+        let (renamed, defines_force) = rename(&module.source, module);
+        if !defines_force {
+            return Err(format!("force module `{}` has no `fn force`", module.name));
+        }
+
+        source += &format!("// {}\n{}\n", module.name, renamed.trim());
+        if module.params.is_some() {
+            source += &format!(
+                "@group(1) @binding({})\nvar<uniform> {} : {};\n",
+                i,
+                module.prefixed("params"),
+                module.prefixed("Params")
+            );
+        }
+        source += "\n";
+    }
+
+    source += "fn custom_force(particle : Particle, index : u32) -> vec2<f32>\n{\n";
+    source += "    var total = vec2<f32>(0.);\n";
+    for module in modules {
+        source += &format!("    total += {}(particle, index);\n", module.prefixed("force"));
+    }
+    source += "    return total;\n}\n";
+    source += &base[end..];
+
+    let (naga_module, info) = shaders::validate(&source, ShaderFile::ParticleCompute)?;
+
+    let read = |name: &str| {
+        naga_module.global_variables.iter().any(|(handle, global)| {
+            global.name.as_deref() == Some(name)
+                && (0..naga_module.entry_points.len())
+                    .any(|i| !info.get_entry_point(i)[handle].is_empty())
+        })
+    };
+    let bound = modules
+        .iter()
+        .enumerate()
+        .filter(|(_, module)| module.params.is_some() && read(&module.prefixed("params")))
+        .map(|(i, _)| i)
+        .collect();
+
+    Ok(Composed { source, bound })
+}
+
+/// Prefixes the identifiers each module declares for itself with the module name, and
+/// whether the source mentions `force` at all.
+fn rename(source: &str, module: &ForceModule) -> (String, bool) {
+    let mut renamed = String::with_capacity(source.len());
+    let mut defines_force = false;
+    let mut rest = source;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        renamed += &rest[..start];
+        rest = &rest[start..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let identifier = &rest[..len];
+
+        match identifier {
+            "force" | "Params" | "params" => {
+                defines_force |= identifier == "force";
+                renamed += &module.prefixed(identifier);
+            }
+            _ => renamed += identifier,
+        }
+        rest = &rest[len..];
+    }
+    renamed += rest;
+
+    (renamed, defines_force)
+}
@@ -1,37 +1,255 @@
+use std::{collections::VecDeque, io::Write};
+
+use super::profiler::GpuTimes;
+
+/// Number of frames kept by [`FPSCounter::new`].
+const DEFAULT_WINDOW : usize = 240;
+
+/// Time spent on one frame, all in seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameSample
+{
+    /// Number of the frame, counting every frame since the counter was created.
+    pub frame : u64,
+    /// The whole frame, as passed to [`FPSCounter::add_frametime`].
+    pub frametime : f32,
+    /// Writing uniforms before the frame.
+    pub update : f32,
+    /// Recording the compute and render passes.
+    pub encode : f32,
+    /// Submitting the commands and presenting.
+    pub submit : f32,
+    /// Time spent on the GPU, when timestamp queries are supported.
+    pub gpu : Option<GpuTimes>,
+    /// Simulated seconds of the latest step read back, see [`FPSCounter::add_timestep`].
+    pub timestep : Option<f32>,
+}
+
+impl FrameSample
+{
+    /// CPU time spent on the frame by the engine itself.
+    pub fn cpu(&self) -> f32
+    {
+        self.update + self.encode + self.submit
+    }
+}
+
+/// Frame time statistics over the window of an [`FPSCounter`], all in seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats
+{
+    pub min : f32,
+    pub max : f32,
+    pub mean : f32,
+    pub median : f32,
+    pub p95 : f32,
+    pub p99 : f32,
+}
+
+impl FrameStats
+{
+    /// Frames per second going by the mean frame time.
+    pub fn fps(&self) -> f32
+    {
+        if self.mean > 0. { 1. / self.mean } else { 0. }
+    }
+}
+
+/// Where CPU time was spent during a frame, see [`FPSCounter::add_stage_time`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameStage
+{
+    Update,
+    Encode,
+    Submit,
+}
+
+/// A rolling history of frame times.
 pub struct FPSCounter
 {
-    last_60_frames : [f32; 60],
-    size : usize,
-    i : usize
+    history : VecDeque<FrameSample>,
+    window : usize,
+    /// Stage times of the frame in progress.
+    current : FrameSample,
+    /// Frames finished so far, including those that left the window.
+    frames : u64,
 }
 
 impl FPSCounter
 {
     pub fn new() -> Self
     {
+        Self::with_window(DEFAULT_WINDOW)
+    }
+
+    /// Keeps the last `window` frames.
+    pub fn with_window(window : usize) -> Self
+    {
+        let window = window.max(1);
         Self
         {
-            last_60_frames : [0.; 60],
-            size : 0,
-            i : 0,
+            history : VecDeque::with_capacity(window),
+            window,
+            current : FrameSample::default(),
+            frames : 0,
         }
     }
 
+    pub fn window(&self) -> usize
+    {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window : usize)
+    {
+        self.window = window.max(1);
+        while self.history.len() > self.window
+        {
+            self.history.pop_front();
+        }
+    }
+
+    /// Adds to the time spent in `stage` this frame.
+    pub fn add_stage_time(&mut self, stage : FrameStage, dt : f32)
+    {
+        match stage
+        {
+            FrameStage::Update => self.current.update += dt,
+            FrameStage::Encode => self.current.encode += dt,
+            FrameStage::Submit => self.current.submit += dt,
+        }
+    }
+
+    /// Attaches GPU times to this frame. They are measured a frame or two earlier,
+    /// see [`GpuProfiler`](super::profiler::GpuProfiler).
+    pub fn add_gpu_times(&mut self, times : GpuTimes)
+    {
+        self.current.gpu = Some(times);
+    }
+
+    /// Attaches the simulation timestep to this frame. With an adaptive timestep it is
+    /// read back from the GPU, so it belongs to a step a frame or two earlier.
+    pub fn add_timestep(&mut self, dt : f32)
+    {
+        self.current.timestep = Some(dt);
+    }
+
+    /// Finishes the frame, pushing it along with its stage times into the history.
     pub fn add_frametime(&mut self, dt : f32)
     {
-        self.last_60_frames[self.i % 60] = dt;
-        self.i += 1;
-        if self.size < 60
+        let sample = FrameSample { frame : self.frames, frametime : dt, ..std::mem::take(&mut self.current) };
+        self.frames += 1;
+
+        if self.history.len() == self.window
         {
-            self.size += 1;
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+    }
+
+    /// The frames in the window, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &FrameSample>
+    {
+        self.history.iter()
+    }
+
+    /// Statistics of the whole frame time.
+    pub fn stats(&self) -> FrameStats
+    {
+        Self::stats_of(self.history.iter().map(|s| s.frametime).collect())
+    }
+
+    /// Statistics of the CPU time spent updating, encoding and submitting.
+    pub fn cpu_stats(&self) -> FrameStats
+    {
+        Self::stats_of(self.history.iter().map(FrameSample::cpu).collect())
+    }
+
+    /// Statistics of the total GPU time, `None` without any GPU times in the window.
+    pub fn gpu_stats(&self) -> Option<FrameStats>
+    {
+        let times : Vec<f32> = self.history.iter().filter_map(|s| s.gpu).map(|g| g.total()).collect();
+        (!times.is_empty()).then(|| Self::stats_of(times))
+    }
+
+    fn stats_of(mut frametimes : Vec<f32>) -> FrameStats
+    {
+        if frametimes.is_empty()
+        {
+            return FrameStats::default();
+        }
+
+        frametimes.sort_by(f32::total_cmp);
+
+        // Nearest rank percentile
+        let percentile = |p : f32|
+        {
+            let rank = (p * frametimes.len() as f32).ceil() as usize;
+            frametimes[rank.clamp(1, frametimes.len()) - 1]
+        };
+
+        FrameStats
+        {
+            min : frametimes[0],
+            max : frametimes[frametimes.len() - 1],
+            mean : frametimes.iter().sum::<f32>() / frametimes.len() as f32,
+            median : percentile(0.5),
+            p95 : percentile(0.95),
+            p99 : percentile(0.99),
         }
     }
 
     pub fn get_fps(&self) -> u32
     {
-        let sum : f32 = self.last_60_frames.iter().sum();
-        let fps = 1. / (sum / self.size.max(1) as f32);
+        self.stats().fps().round() as u32
+    }
 
-        fps.ceil() as u32
+    /// Writes the history as CSV with a header row, times in milliseconds. Frames are
+    /// numbered from the first one counted, so exports taken at different times line up.
+    /// The GPU columns are left empty for frames without GPU times.
+    pub fn write_csv(&self, mut writer : impl Write) -> std::io::Result<()>
+    {
+        writeln!(
+            writer,
+            "frame,frametime_ms,update_ms,encode_ms,submit_ms,timestep_ms,gpu_simulate_ms,gpu_colour_ms,gpu_heatmap_ms,gpu_draw_ms"
+        )?;
+        for sample in &self.history
+        {
+            write!(
+                writer,
+                "{},{:.4},{:.4},{:.4},{:.4}",
+                sample.frame,
+                sample.frametime * 1000.,
+                sample.update * 1000.,
+                sample.encode * 1000.,
+                sample.submit * 1000.,
+            )?;
+            match sample.timestep
+            {
+                Some(dt) => write!(writer, ",{:.4}", dt * 1000.)?,
+                None => write!(writer, ",")?,
+            }
+            match sample.gpu
+            {
+                Some(gpu) => writeln!(
+                    writer,
+                    ",{:.4},{:.4},{:.4},{:.4}",
+                    gpu.simulate * 1000.,
+                    gpu.colour * 1000.,
+                    gpu.heatmap * 1000.,
+                    gpu.draw * 1000.,
+                )?,
+                None => writeln!(writer, ",,,,")?,
+            }
+        }
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl Default for FPSCounter
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
@@ -0,0 +1,313 @@
+//! Reproducible initial conditions. Every generator draws from an [`Rng`] seeded by
+//! the caller, so the same seed always gives the same particles.
+
+use std::f32::consts::TAU;
+
+/// A small, fast and seedable generator (SplitMix64), good enough for initial conditions.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `[min, max)`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// A standard normal sample, using the Box-Muller transform.
+    pub fn normal(&mut self) -> f32 {
+        let u = 1. - self.next_f32();
+        let v = self.next_f32();
+        (-2. * u.ln()).sqrt() * (TAU * v).cos()
+    }
+
+    /// A direction uniformly distributed over the unit sphere.
+    fn unit_sphere(&mut self) -> [f32; 3] {
+        let z = self.range(-1., 1.);
+        let angle = self.range(0., TAU);
+        let r = (1. - z * z).sqrt();
+        [r * angle.cos(), r * angle.sin(), z]
+    }
+}
+
+/// A generated particle, velocities are in world units per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sample {
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+}
+
+impl Sample {
+    fn at_rest(position: [f32; 2]) -> Self {
+        Self {
+            position,
+            velocity: [0., 0.],
+        }
+    }
+}
+
+/// Speed of a circular orbit at `radius` around an attractor of `strength` and `falloff`,
+/// see [`Attractor`](super::Attractor).
+pub fn circular_speed(radius: f32, strength: f32, falloff: f32, mass: f32) -> f32 {
+    (strength * radius.powf(1. - falloff) / mass).max(0.).sqrt()
+}
+
+/// Orbits `center` anticlockwise at `speed` from `offset`.
+fn orbit(center: [f32; 2], offset: [f32; 2], speed: f32) -> Sample {
+    let radius = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+    let tangent = if radius > 0. {
+        [-offset[1] / radius, offset[0] / radius]
+    } else {
+        [0., 0.]
+    };
+    Sample {
+        position: [center[0] + offset[0], center[1] + offset[1]],
+        velocity: [tangent[0] * speed, tangent[1] * speed],
+    }
+}
+
+/// Uniformly random in the rectangle between `min` and `max`, at rest.
+pub fn uniform_box(rng: &mut Rng, count: usize, min: [f32; 2], max: [f32; 2]) -> Vec<Sample> {
+    (0..count)
+        .map(|_| Sample::at_rest([rng.range(min[0], max[0]), rng.range(min[1], max[1])]))
+        .collect()
+}
+
+/// Uniformly random over a disc, at rest.
+pub fn uniform_disc(rng: &mut Rng, count: usize, center: [f32; 2], radius: f32) -> Vec<Sample> {
+    (0..count)
+        .map(|_| {
+            let r = radius * rng.next_f32().sqrt();
+            let angle = rng.range(0., TAU);
+            Sample::at_rest([center[0] + r * angle.cos(), center[1] + r * angle.sin()])
+        })
+        .collect()
+}
+
+/// Normally distributed around `center` with standard deviation `sigma`, at rest.
+pub fn gaussian(rng: &mut Rng, count: usize, center: [f32; 2], sigma: f32) -> Vec<Sample> {
+    (0..count)
+        .map(|_| Sample::at_rest([center[0] + rng.normal() * sigma, center[1] + rng.normal() * sigma]))
+        .collect()
+}
+
+/// A Plummer sphere projected onto the plane, with velocities from its distribution function
+/// for a total gravitational parameter of `strength`.
+///
+/// Follows Aarseth, Henon & Wielen (1974). The radius is capped at ten scale radii to avoid
+/// the few particles the distribution throws far away.
+pub fn plummer(
+    rng: &mut Rng,
+    count: usize,
+    center: [f32; 2],
+    scale_radius: f32,
+    strength: f32,
+) -> Vec<Sample> {
+    let velocity_scale = (strength / scale_radius).max(0.).sqrt();
+
+    (0..count)
+        .map(|_| {
+            // In units of the scale radius
+            let r = loop {
+                let m = rng.next_f32().max(f32::MIN_POSITIVE);
+                let r = (m.powf(-2. / 3.) - 1.).max(0.).powf(-0.5);
+                if r < 10. {
+                    break r;
+                }
+            };
+            let q = loop {
+                let q = rng.next_f32();
+                let g = q * q * (1. - q * q).powf(3.5);
+                if rng.next_f32() * 0.1 < g {
+                    break q;
+                }
+            };
+            let escape = 2f32.sqrt() * (1. + r * r).powf(-0.25);
+            let speed = q * escape * velocity_scale;
+
+            let [px, py, _] = rng.unit_sphere();
+            let [vx, vy, _] = rng.unit_sphere();
+            Sample {
+                position: [
+                    center[0] + px * r * scale_radius,
+                    center[1] + py * r * scale_radius,
+                ],
+                velocity: [vx * speed, vy * speed],
+            }
+        })
+        .collect()
+}
+
+/// Orbit parameters shared by the rotating generators.
+#[derive(Clone, Copy, Debug)]
+pub struct Orbits {
+    /// Strength and falloff of the attractor at the centre, see [`Attractor`](super::Attractor).
+    pub strength: f32,
+    pub falloff: f32,
+    /// Mass of the orbiting particles.
+    pub mass: f32,
+    pub clockwise: bool,
+}
+
+impl Orbits {
+    fn sample(&self, center: [f32; 2], offset: [f32; 2]) -> Sample {
+        let radius = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+        let mut speed = circular_speed(radius, self.strength, self.falloff, self.mass);
+        if self.clockwise {
+            speed = -speed;
+        }
+        orbit(center, offset, speed)
+    }
+}
+
+/// A disc whose surface density falls off as `exp(-r / scale_length)`, out to `radius`,
+/// with every particle on a circular orbit around the centre. Without a positive
+/// `radius` and `scale_length` every particle is at the centre.
+pub fn exponential_disc(
+    rng: &mut Rng,
+    count: usize,
+    center: [f32; 2],
+    scale_length: f32,
+    radius: f32,
+    orbits: Orbits,
+) -> Vec<Sample> {
+    // The radial distribution r exp(-r / h) is a gamma distribution of shape 2, truncated at
+    // `radius`. Rejecting gamma draws beyond the cut only pays off when most lie within it,
+    // for small discs draw uniformly over the area and reject by the exponential instead.
+    // Either way at least a fifth of the draws are kept.
+    let cut = radius / scale_length;
+    let within = 1. - (1. + cut) * (-cut).exp();
+    let valid = radius > 0. && scale_length > 0.;
+
+    (0..count)
+        .map(|_| {
+            let r = match valid {
+                false => 0.,
+                true if within >= 0.5 => loop {
+                    let u = (1. - rng.next_f32()) * (1. - rng.next_f32());
+                    let r = -scale_length * u.ln();
+                    if r <= radius {
+                        break r;
+                    }
+                },
+                true => loop {
+                    let r = radius * rng.next_f32().sqrt();
+                    if rng.next_f32() < (-r / scale_length).exp() {
+                        break r;
+                    }
+                },
+            };
+            let angle = rng.range(0., TAU);
+            orbits.sample(center, [r * angle.cos(), r * angle.sin()])
+        })
+        .collect()
+}
+
+/// Particles spread evenly over the area of an annulus, each on a circular orbit around the
+/// central mass.
+pub fn keplerian_ring(
+    rng: &mut Rng,
+    count: usize,
+    center: [f32; 2],
+    inner_radius: f32,
+    outer_radius: f32,
+    orbits: Orbits,
+) -> Vec<Sample> {
+    let inner = inner_radius * inner_radius;
+    let outer = outer_radius * outer_radius;
+
+    (0..count)
+        .map(|_| {
+            let r = rng.range(inner, outer).sqrt();
+            let angle = rng.range(0., TAU);
+            orbits.sample(center, [r * angle.cos(), r * angle.sin()])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORBITS: Orbits = Orbits {
+        strength: 54000.,
+        falloff: 1.,
+        mass: 1.,
+        clockwise: false,
+    };
+
+    fn generate(seed: u64) -> Vec<Vec<Sample>> {
+        let center = [100., 200.];
+        vec![
+            uniform_box(&mut Rng::new(seed), 100, [0., 0.], [10., 20.]),
+            uniform_disc(&mut Rng::new(seed), 100, center, 50.),
+            gaussian(&mut Rng::new(seed), 100, center, 5.),
+            plummer(&mut Rng::new(seed), 100, center, 10., 54000.),
+            exponential_disc(&mut Rng::new(seed), 100, center, 20., 60., ORBITS),
+            keplerian_ring(&mut Rng::new(seed), 100, center, 30., 40., ORBITS),
+        ]
+    }
+
+    #[test]
+    fn same_seed_same_samples() {
+        assert_eq!(generate(7), generate(7));
+        for (a, b) in generate(7).iter().zip(generate(8)) {
+            assert_ne!(*a, b);
+        }
+    }
+
+    #[test]
+    fn circular_speed_balances_attraction() {
+        for (radius, strength, falloff, mass) in [
+            (1., 1., 1., 1.),
+            (250., 54000., 1., 2.),
+            (40., 300., 2., 0.5),
+            (7., 10., 0., 3.),
+        ] {
+            let v = circular_speed(radius, strength, falloff, mass);
+            let centripetal = v * v / radius;
+            let attraction = strength * radius.powf(-falloff) / mass;
+            assert!(
+                (centripetal - attraction).abs() <= attraction * 1e-5,
+                "{} != {}",
+                centripetal,
+                attraction
+            );
+        }
+    }
+
+    #[test]
+    fn exponential_disc_stays_within_radius() {
+        for (scale_length, radius) in [(20., 60.), (20., 0.5), (1e-3, 1e-4), (20., 0.), (0., 10.)] {
+            for sample in exponential_disc(
+                &mut Rng::new(1),
+                1000,
+                [0., 0.],
+                scale_length,
+                radius,
+                ORBITS,
+            ) {
+                let [x, y] = sample.position;
+                assert!((x * x + y * y).sqrt() <= radius.max(0.) * 1.0001);
+            }
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, FragmentState, PipelineCompilationOptions, PipelineLayout,
+    PipelineLayoutDescriptor, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModule, ShaderStages, TextureFormat, TextureSampleType, TextureViewDimension,
+};
+
+use crate::SIDE_LENGTH;
+
+use super::{
+    colour::{colour_map_sampler, colour_map_view, ColourMap},
+    compute::workgroups,
+    profiler::{GpuPass, GpuProfiler},
+};
+
+/// Size of a heatmap cell in world units.
+const HEATMAP_CELL_SIZE: f32 = 2.;
+
+/// What each particle adds to its heatmap cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeatmapWeight {
+    Count,
+    Mass,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct HeatmapUniforms {
+    count: u32,
+    grid_width: u32,
+    grid_height: u32,
+    weight: u32,
+    cell_size: f32,
+    _padding: [u32; 3],
+}
+
+/// Splats particles into a world space grid and draws it through a log scaled colour map.
+///
+/// Replaces drawing the particles themselves, which gets expensive and hides
+/// structure once millions of sprites overlap.
+pub struct Heatmap {
+    uniforms: HeatmapUniforms,
+    uniform_buffer: Buffer,
+    cell_buffer: Buffer,
+
+    compute_bind_group: BindGroup,
+    clear_pipeline: ComputePipeline,
+    splat_pipeline: ComputePipeline,
+
+    shader: ShaderModule,
+    render_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    render_bind_group: BindGroup,
+    render_pipeline: RenderPipeline,
+}
+
+impl Heatmap {
+    pub fn new(
+        particles: &Buffer,
+        count: u32,
+        map: &ColourMap,
+        camera_layout: &BindGroupLayout,
+        format: TextureFormat,
+        device: &Device,
+        queue: &Queue,
+    ) -> Self {
+        let grid_size = (SIDE_LENGTH as f32 / HEATMAP_CELL_SIZE).ceil() as u32;
+        let uniforms = HeatmapUniforms {
+            count,
+            grid_width: grid_size,
+            grid_height: grid_size,
+            weight: 0,
+            cell_size: HEATMAP_CELL_SIZE,
+            _padding: [0; 3],
+        };
+
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Heatmap Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniforms]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        // The cells followed by the largest of them
+        let cell_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Heatmap Cell Buffer"),
+            size: (grid_size as u64 * grid_size as u64 + 1) * 4,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let buffer_entry = |binding, visibility, ty| BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let compute_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Heatmap Compute Bind Group Layout"),
+            entries: &[
+                buffer_entry(0, ShaderStages::COMPUTE, BufferBindingType::Uniform),
+                buffer_entry(
+                    1,
+                    ShaderStages::COMPUTE,
+                    BufferBindingType::Storage { read_only: true },
+                ),
+                buffer_entry(
+                    2,
+                    ShaderStages::COMPUTE,
+                    BufferBindingType::Storage { read_only: false },
+                ),
+            ],
+        });
+
+        let compute_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Heatmap Compute Bind Group"),
+            layout: &compute_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: particles.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: cell_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("heatmap.wgsl"));
+        let compute_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Heatmap Compute Layout"),
+            bind_group_layouts: &[&compute_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&compute_pipeline_layout),
+                module: &shader,
+                entry_point,
+                compilation_options: PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+        let clear_pipeline = pipeline("clear");
+        let splat_pipeline = pipeline("splat");
+
+        let render_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Heatmap Render Bind Group Layout"),
+            entries: &[
+                buffer_entry(
+                    0,
+                    ShaderStages::VERTEX_FRAGMENT,
+                    BufferBindingType::Uniform,
+                ),
+                buffer_entry(
+                    3,
+                    ShaderStages::FRAGMENT,
+                    BufferBindingType::Storage { read_only: true },
+                ),
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D1,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Heatmap Render Layout"),
+            bind_group_layouts: &[&render_layout, camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_bind_group = Self::create_render_bind_group(
+            map,
+            &uniform_buffer,
+            &cell_buffer,
+            &render_layout,
+            device,
+            queue,
+        );
+        let render_pipeline = Self::create_render_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            format,
+            BlendState::ALPHA_BLENDING,
+        );
+
+        Self {
+            uniforms,
+            uniform_buffer,
+            cell_buffer,
+            compute_bind_group,
+            clear_pipeline,
+            splat_pipeline,
+            shader,
+            render_layout,
+            pipeline_layout,
+            render_bind_group,
+            render_pipeline,
+        }
+    }
+
+    fn create_render_bind_group(
+        map: &ColourMap,
+        uniform_buffer: &Buffer,
+        cell_buffer: &Buffer,
+        layout: &BindGroupLayout,
+        device: &Device,
+        queue: &Queue,
+    ) -> BindGroup {
+        let view = colour_map_view(map, device, queue);
+        let sampler = colour_map_sampler(device);
+
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Heatmap Render Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: cell_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        layout: &PipelineLayout,
+        shader: &ShaderModule,
+        format: TextureFormat,
+        blend: BlendState,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Heatmap Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_grid",
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "fs_grid",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    pub fn weight(&self) -> HeatmapWeight {
+        match self.uniforms.weight {
+            0 => HeatmapWeight::Count,
+            _ => HeatmapWeight::Mass,
+        }
+    }
+
+    pub fn set_weight(&mut self, weight: HeatmapWeight, queue: &Queue) {
+        self.uniforms.weight = match weight {
+            HeatmapWeight::Count => 0,
+            HeatmapWeight::Mass => 1,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
+    }
+
+    pub fn set_map(&mut self, map: &ColourMap, device: &Device, queue: &Queue) {
+        self.render_bind_group = Self::create_render_bind_group(
+            map,
+            &self.uniform_buffer,
+            &self.cell_buffer,
+            &self.render_layout,
+            device,
+            queue,
+        );
+    }
+
+    pub fn set_target(&mut self, format: TextureFormat, blend: BlendState, device: &Device) {
+        self.render_pipeline =
+            Self::create_render_pipeline(device, &self.pipeline_layout, &self.shader, format, blend);
+    }
+
+    pub fn compute(&self, encoder: &mut CommandEncoder, profiler: Option<&GpuProfiler>) {
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("Heatmap Splat"),
+            timestamp_writes: profiler.map(|p| p.compute_writes(GpuPass::Heatmap)),
+        });
+        pass.set_bind_group(0, &self.compute_bind_group, &[]);
+
+        let (x, y) = workgroups(self.uniforms.grid_width * self.uniforms.grid_height + 1);
+        pass.set_pipeline(&self.clear_pipeline);
+        pass.dispatch_workgroups(x, y, 1);
+
+        let (x, y) = workgroups(self.uniforms.count);
+        pass.set_pipeline(&self.splat_pipeline);
+        pass.dispatch_workgroups(x, y, 1);
+    }
+
+    /// Draws the grid, the camera is expected in bind group 1.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,132 @@
+struct Particle
+{
+    old_position : vec2<f32>,
+    position : vec2<f32>,
+    colour : vec4<f32>,
+    mass : f32,
+    radius : f32,
+    species : u32,
+    flags : u32,
+    age : f32,
+    lifetime : f32,
+}
+
+struct Uniforms
+{
+    count : u32,
+    grid_width : u32,
+    grid_height : u32,
+    weight : u32,
+    cell_size : f32,
+}
+
+@group(0) @binding(0)
+var<uniform> uniforms : Uniforms;
+
+@group(0) @binding(1)
+var<storage, read> particles : array<Particle>;
+
+// Grid cells, followed by the largest cell
+@group(0) @binding(2)
+var<storage, read_write> cells : array<atomic<u32>>;
+
+const MASS : u32 = 1u;
+// Masses are accumulated in fixed point
+const MASS_SCALE : f32 = 256.;
+
+fn thread_index(global_id : vec3<u32>, groups : vec3<u32>) -> u32
+{
+    return global_id.x + (global_id.y * groups.x * 64u);
+}
+
+@compute
+@workgroup_size(64)
+fn clear(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index <= uniforms.grid_width * uniforms.grid_height
+    {
+        atomicStore(&cells[index], 0u);
+    }
+}
+
+@compute
+@workgroup_size(64)
+fn splat(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index >= uniforms.count
+    {
+        return;
+    }
+
+    let cell = vec2<i32>(floor(particles[index].position / uniforms.cell_size));
+    if cell.x < 0 || cell.y < 0 || cell.x >= i32(uniforms.grid_width) || cell.y >= i32(uniforms.grid_height)
+    {
+        return;
+    }
+
+    var weight = 1u;
+    if uniforms.weight == MASS
+    {
+        weight = u32(max(particles[index].mass * MASS_SCALE, 1.));
+    }
+
+    let total = atomicAdd(&cells[u32(cell.x) + u32(cell.y) * uniforms.grid_width], weight) + weight;
+    atomicMax(&cells[uniforms.grid_width * uniforms.grid_height], total);
+}
+
+struct VertexOutput
+{
+    @builtin(position) clip_position : vec4<f32>,
+    @location(0) grid : vec2<f32>,
+}
+
+struct CameraUniform {
+    proj_view: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// The render pipeline sees the cells as plain integers
+@group(0) @binding(3)
+var<storage, read> totals : array<u32>;
+@group(0) @binding(4)
+var colour_map : texture_1d<f32>;
+@group(0) @binding(5)
+var colour_map_sampler : sampler;
+
+// A quad covering the grid in world space
+@vertex
+fn vs_grid(@builtin(vertex_index) index : u32) -> VertexOutput
+{
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0., 0.),
+        vec2<f32>(1., 0.),
+        vec2<f32>(1., 1.),
+        vec2<f32>(0., 0.),
+        vec2<f32>(1., 1.),
+        vec2<f32>(0., 1.),
+    );
+
+    var out : VertexOutput;
+    let grid = corners[index] * vec2<f32>(f32(uniforms.grid_width), f32(uniforms.grid_height));
+    out.clip_position = camera.proj_view * vec4<f32>(grid * uniforms.cell_size, 0., 1.);
+    out.grid = grid;
+    return out;
+}
+
+@fragment
+fn fs_grid(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    let cell = min(vec2<u32>(in.grid), vec2<u32>(uniforms.grid_width, uniforms.grid_height) - 1u);
+    let total = totals[cell.x + cell.y * uniforms.grid_width];
+    if total == 0u
+    {
+        return vec4<f32>(0.);
+    }
+
+    let largest = f32(totals[uniforms.grid_width * uniforms.grid_height]);
+    let t = log(1. + f32(total)) / log(1. + largest);
+    return textureSampleLevel(colour_map, colour_map_sampler, t, 0.);
+}
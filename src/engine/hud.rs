@@ -0,0 +1,307 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferDescriptor,
+    BufferUsages, Device, FragmentState, PipelineCompilationOptions, PipelineLayoutDescriptor,
+    Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderStages, TextureFormat,
+    VertexBufferLayout, VertexStepMode,
+};
+use winit::dpi::PhysicalSize;
+
+/// Upper bound on rectangles drawn per frame, anything past it is dropped.
+const MAX_RECTS: usize = 8192;
+
+/// Screen pixels per font pixel.
+const TEXT_SCALE: f32 = 2.;
+const GLYPH_WIDTH: f32 = 3.;
+const GLYPH_HEIGHT: f32 = 5.;
+const MARGIN: f32 = 8.;
+
+const GRAPH_HEIGHT: f32 = 48.;
+const GRAPH_BAR_WIDTH: f32 = 2.;
+/// Frame time the graph is scaled to at least, so a steady frame rate doesn't fill it.
+const GRAPH_MIN_RANGE: f32 = 1. / 30.;
+/// Frame time the reference line is drawn at.
+const GRAPH_TARGET: f32 = 1. / 60.;
+
+const TEXT_COLOUR: [f32; 4] = [1., 1., 1., 1.];
+const PANEL_COLOUR: [f32; 4] = [0., 0., 0., 0.6];
+const GOOD_FRAME_COLOUR: [f32; 4] = [0.3, 0.9, 0.4, 1.];
+const SLOW_FRAME_COLOUR: [f32; 4] = [0.95, 0.35, 0.3, 1.];
+const TARGET_COLOUR: [f32; 4] = [1., 1., 1., 0.4];
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct Rect {
+    /// Top left corner and size in pixels.
+    rect: [f32; 4],
+    colour: [f32; 4],
+}
+
+impl Rect {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4];
+
+    fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Rect>() as wgpu::BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Rows of a 3x5 glyph, the most significant of the three bits is the left column.
+/// Lowercase letters are drawn as uppercase.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0; 5],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct HudUniform {
+    screen_size: [f32; 2],
+}
+
+/// A debug overlay of text lines above a frame time graph, drawn as solid rectangles
+/// with a built in bitmap font.
+pub struct Hud {
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    rect_buffer: Buffer,
+    pipeline: RenderPipeline,
+
+    rects: Vec<Rect>,
+}
+
+impl Hud {
+    pub fn new(format: TextureFormat, device: &Device) -> Self {
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("HUD Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[HudUniform {
+                screen_size: [1., 1.],
+            }]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("HUD Bind Group Layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("HUD Bind Group"),
+            layout: &layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let rect_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("HUD Rect Buffer"),
+            size: (MAX_RECTS * std::mem::size_of::<Rect>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("hud.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("HUD Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("HUD Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_rect",
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[Rect::desc()],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_rect",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            uniform_buffer,
+            bind_group,
+            rect_buffer,
+            pipeline,
+            rects: Vec::new(),
+        }
+    }
+
+    fn push_rect(&mut self, x: f32, y: f32, width: f32, height: f32, colour: [f32; 4]) {
+        self.rects.push(Rect {
+            rect: [x, y, width, height],
+            colour,
+        });
+    }
+
+    fn push_text(&mut self, text: &str, x: f32, y: f32) {
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i as f32 * (GLYPH_WIDTH + 1.) * TEXT_SCALE;
+            for (row, bits) in glyph(c).into_iter().enumerate() {
+                for column in 0..GLYPH_WIDTH as u32 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.push_rect(
+                            left + column as f32 * TEXT_SCALE,
+                            y + row as f32 * TEXT_SCALE,
+                            TEXT_SCALE,
+                            TEXT_SCALE,
+                            TEXT_COLOUR,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lays out `lines` in the top left corner with a graph of `frametimes` (in seconds,
+    /// oldest first) below them.
+    pub fn prepare(
+        &mut self,
+        lines: &[String],
+        frametimes: &[f32],
+        size: PhysicalSize<u32>,
+        queue: &Queue,
+    ) {
+        self.rects.clear();
+
+        let line_height = (GLYPH_HEIGHT + 2.) * TEXT_SCALE;
+        let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let text_width = longest as f32 * (GLYPH_WIDTH + 1.) * TEXT_SCALE;
+        let graph_width = frametimes.len() as f32 * GRAPH_BAR_WIDTH;
+        let graph_top = MARGIN * 2. + lines.len() as f32 * line_height;
+
+        self.push_rect(
+            MARGIN,
+            MARGIN,
+            text_width.max(graph_width) + MARGIN * 2.,
+            graph_top + GRAPH_HEIGHT,
+            PANEL_COLOUR,
+        );
+
+        for (i, line) in lines.iter().enumerate() {
+            self.push_text(line, MARGIN * 2., MARGIN * 2. + i as f32 * line_height);
+        }
+
+        let range = frametimes.iter().copied().fold(GRAPH_MIN_RANGE, f32::max);
+        let graph_bottom = graph_top + GRAPH_HEIGHT;
+        for (i, frametime) in frametimes.iter().enumerate() {
+            let height = (frametime / range).min(1.) * GRAPH_HEIGHT;
+            let colour = if *frametime <= GRAPH_TARGET * 1.5 {
+                GOOD_FRAME_COLOUR
+            } else {
+                SLOW_FRAME_COLOUR
+            };
+            self.push_rect(
+                MARGIN * 2. + i as f32 * GRAPH_BAR_WIDTH,
+                graph_bottom - height,
+                GRAPH_BAR_WIDTH,
+                height,
+                colour,
+            );
+        }
+        self.push_rect(
+            MARGIN * 2.,
+            graph_bottom - GRAPH_TARGET / range * GRAPH_HEIGHT,
+            graph_width,
+            1.,
+            TARGET_COLOUR,
+        );
+
+        self.rects.truncate(MAX_RECTS);
+        queue.write_buffer(&self.rect_buffer, 0, bytemuck::cast_slice(&self.rects));
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[HudUniform {
+                screen_size: [size.width as f32, size.height as f32],
+            }]),
+        );
+    }
+
+    /// Draws what was last prepared on top of whatever `pass` drew so far. The pass has to
+    /// target the surface format.
+    pub fn draw(&self, pass: &mut RenderPass<'_>) {
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.rect_buffer.slice(..));
+        pass.draw(0..6, 0..self.rects.len() as u32);
+    }
+}
@@ -0,0 +1,45 @@
+struct VertexOutput
+{
+    @builtin(position) clip_position : vec4<f32>,
+    @location(0) colour : vec4<f32>,
+}
+
+struct HudUniform
+{
+    screen_size : vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> hud : HudUniform;
+
+// Rectangles in pixels from the top left of the screen
+@vertex
+fn vs_rect(
+    @builtin(vertex_index) index : u32,
+    @location(0) rect : vec4<f32>,
+    @location(1) colour : vec4<f32>,
+) -> VertexOutput
+{
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0., 0.),
+        vec2<f32>(0., 1.),
+        vec2<f32>(1., 1.),
+        vec2<f32>(0., 0.),
+        vec2<f32>(1., 1.),
+        vec2<f32>(1., 0.),
+    );
+
+    let pixel = rect.xy + corners[index] * rect.zw;
+    let ndc = pixel / hud.screen_size * 2. - 1.;
+
+    var out : VertexOutput;
+    out.clip_position = vec4<f32>(ndc.x, -ndc.y, 0., 1.);
+    out.colour = colour;
+    return out;
+}
+
+@fragment
+fn fs_rect(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    return in.colour;
+}
@@ -1,92 +1,188 @@
-use std::{mem::size_of, time::Instant};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use env_logger::filter::Filter;
+use bytemuck::Pod;
+use image::RgbaImage;
 use vecto_rs::linear::{Vector, VectorTrait};
 use wgpu::{
-    core::instance,
-    util::{BufferInitDescriptor, DeviceExt},
-    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
-    BufferSize, BufferUsages, Color, FilterMode, FragmentState, ImageCopyTextureBase, Operations,
-    Origin3d, PipelineCompilationOptions, PipelineLayoutDescriptor, PresentMode,
-    RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor, SamplerBindingType,
-    SamplerDescriptor, ShaderModuleDescriptor, ShaderStages, StoreOp, Texture, TextureDescriptor,
-    TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension,
+    Color, Operations, PresentMode, RenderPassColorAttachment, RenderPassDescriptor, StoreOp,
+};
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
 };
-use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
 
 use super::{
-    compute::ParticleCompute, fps::FPSCounter, Camera, ParticleInstance, RawParticleInstance,
-    Vertex,
+    adapter::{self, AdapterSelection},
+    capture::OffscreenTarget,
+    colour::{ColourMap, ColourMode, ColourRange, ParticleColouring},
+    compute::{self, ParticleCompute},
+    diagnostics::{Diagnostics, Reduction},
+    field::VectorField,
+    forces::ForceModule,
+    fps::{FPSCounter, FrameStage, FrameStats},
+    heatmap::{Heatmap, HeatmapWeight},
+    hud::Hud,
+    pipeline::{ParticlePipeline, RenderMode, ADDITIVE_BLENDING},
+    post::{HdrSettings, PostProcess, HDR_FORMAT},
+    profiler::{GpuPass, GpuProfiler},
+    recorder::Recorder,
+    scene::{AdaptiveTimestep, Integrator, Scene},
+    shaders::{self, ShaderFile, ShaderWatcher, SHADER_DIR},
+    sprite::{ParticleSprite, SpriteAtlas},
+    trails::{Trails, TRAIL_FORMAT},
+    Camera,
+};
+use crate::{Error, SIDE_LENGTH};
+
+const BACKGROUND: Color = Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
 };
-use crate::SIDE_LENGTH;
-
-const TRIANGLE_VERTS: &[Vertex] = &[
-    Vertex {
-        position: [0.86603, 1.5, 0.0],
-        tex_coords: [0.5, 1. - 0.866025],
-    },
-    Vertex {
-        position: [0.0, 0.0, 0.0],
-        tex_coords: [0.0, 1.],
-    },
-    Vertex {
-        position: [1.73205, 0.0, 0.0],
-        tex_coords: [1.0, 1.],
-    },
-];
-
-pub struct Instance<'a> {
-    surface: wgpu::Surface<'a>,
+
+/// Zoom per notch of the mouse wheel.
+const ZOOM_STEP: f32 = 1.1;
+
+/// The most storage buffers one shader stage binds, the particle compute pass.
+const STORAGE_BUFFERS_PER_STAGE: u32 = 5;
+
+/// How an [`Instance`] sets up the GPU and where it writes captured files.
+#[derive(Clone, Debug)]
+pub struct InstanceOptions {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    pub adapter: AdapterSelection,
+    /// Whether to fall back to any adapter, the software one and then the other backends
+    /// when the requested adapter isn't available.
+    pub fallback: bool,
+    /// Falls back to a mode the surface supports, see [`Instance::set_present_mode`].
+    pub present_mode: PresentMode,
+    /// Directory screenshots, recordings and frame time logs are saved in.
+    pub output_dir: PathBuf,
+}
+
+impl Default for InstanceOptions {
+    fn default() -> Self {
+        Self {
+            // Primary emits warnings/errors https://github.com/gfx-rs/wgpu/issues/3959, DX12 or Vulkan is fine
+            backends: wgpu::Backends::VULKAN,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            adapter: AdapterSelection::Auto,
+            fallback: true,
+            present_mode: PresentMode::Fifo,
+            output_dir: PathBuf::from("."),
+        }
+    }
+}
+
+/// Runs and draws a scene. A window is optional and can come and go, see
+/// [`Instance::attach_surface`].
+pub struct Instance {
+    /// Creates the surfaces of windows attached later.
+    gpu: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    surface: Option<wgpu::Surface<'static>>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
-    window: &'a winit::window::Window,
+    window: Option<Arc<Window>>,
 
-    particle_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
+    particle_pipeline: ParticlePipeline,
+    post: Option<PostProcess>,
+    /// Post processing at the size screenshots were last taken at, kept for the next.
+    capture_post: Option<(PhysicalSize<u32>, PostProcess)>,
+    trails: Option<Trails>,
     camera: Camera,
-    particle_bind_group: BindGroup,
+    sprite: ParticleSprite,
+    heatmap: Heatmap,
 
+    scene: Scene,
+    /// Where the scene was loaded from, reloading reads it again.
+    scene_path: Option<PathBuf>,
     particle_compute: ParticleCompute,
+    colouring: ParticleColouring,
     mouse_position : Vector,
+    /// World position under the cursor, shown by the HUD.
+    cursor_world: [f32; 2],
+    /// Pixel position of the cursor, from the top left of the window.
+    cursor_screen: [f32; 2],
+    /// Whether the view follows the cursor, while the right or middle button is held.
+    panning: bool,
+
+    /// Number of simulation steps taken so far.
+    step: u64,
+    recorder: Option<Recorder>,
+
+    /// Where captured files are saved.
+    output_dir: PathBuf,
 
     fps: FPSCounter,
+    /// Present when GPU profiling is on and the adapter supports timestamp queries.
+    profiler: Option<GpuProfiler>,
+    hud: Option<Hud>,
+
+    /// Present while diagnostics are being computed.
+    reduction: Option<Reduction>,
+    /// The first and latest diagnostics read back since the scene started.
+    diagnostics: Option<(Diagnostics, Diagnostics)>,
+
+    /// Present while the shader sources are watched for changes.
+    shader_watcher: Option<ShaderWatcher>,
+    /// The compute shader last swapped in, applied again when the scene restarts.
+    compute_source: Option<String>,
+    /// Composed into the compute shader, in order.
+    force_modules: Vec<ForceModule>,
+    /// Uploaded again when the scene restarts.
+    vector_field: Option<VectorField>,
+    /// Why the last changed shader was rejected, until one compiles.
+    shader_error: Option<String>,
+
+    /// What the surface supports, empty without one.
+    present_modes: Vec<PresentMode>,
+    /// Frames per second to stay under, if any.
+    frame_limit: Option<f32>,
+    /// When the frame limit allows the next frame.
+    next_frame: Instant,
 }
 
-impl<'a> Instance<'a> {
-    pub async fn new(window: &'a Window) -> Self {
-        let size = window.inner_size();
+/// `mode` when `supported` has it, otherwise the closest mode that is. Fifo is always supported.
+fn supported_present_mode(mode: PresentMode, supported: &[PresentMode]) -> PresentMode {
+    let fallbacks: &[PresentMode] = match mode {
+        // Mailbox doesn't tear either, but still runs as fast as it can
+        PresentMode::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+        PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed],
+        mode => &[mode],
+    };
+    let chosen = fallbacks
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(PresentMode::Fifo);
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::VULKAN, // Primary emits warnings/errors https://github.com/gfx-rs/wgpu/issues/3959, DX12 or Vulkan is fine
-            ..Default::default()
-        });
+    if chosen != mode {
+        log::warn!("{:?} presentation is unsupported, using {:?}", mode, chosen);
+    }
+    chosen
+}
 
-        let surface = instance.create_surface(window).unwrap();
+impl Instance {
+    pub async fn new(window: Arc<Window>, options: InstanceOptions) -> Result<Self, Error> {
+        let size = window.inner_size();
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptionsBase {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+        let (gpu, adapter, surface) = adapter::open(&options, Some(window.clone())).await?;
+        let surface = surface.expect("a surface is created along with the adapter");
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
-                    label: None,
-                    memory_hints: Default::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = Self::request_device(&adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -98,184 +194,182 @@ impl<'a> Instance<'a> {
             .unwrap_or(surface_caps.formats[0]);
 
         let size = if size.width == 0 || size.height == 0 {
-            eprintln!("Width or Height of window is 0, this is invalid!");
-            eprintln!("Defaulting to 100 width and 100 height");
+            log::warn!("The window is {}x{}, starting at 100x100", size.width, size.height);
             PhysicalSize::new(100, 100)
         } else {
             size
         };
 
+        let present_mode = supported_present_mode(options.present_mode, &surface_caps.present_modes);
+
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: PresentMode::Immediate,
+            present_mode,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
 
-        let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Particle Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("particle_shader.wgsl").into()),
-        });
+        surface.configure(&device, &config);
+        let mut instance = Self::with_device(gpu, adapter, device, queue, config, options.output_dir)?;
+        instance.window = Some(window);
+        instance.surface = Some(surface);
+        instance.present_modes = surface_caps.present_modes;
+        Ok(instance)
+    }
 
-        let particle_diffuse = include_bytes!("particle.png");
-        let particle_image = image::load_from_memory(particle_diffuse).unwrap();
-        let particle_rgba = particle_image.to_rgba8();
+    /// Creates an instance without a window, frames are only drawn by
+    /// [`Instance::screenshot`] and the offscreen targets of HDR and trails.
+    pub async fn new_headless(
+        size: PhysicalSize<u32>,
+        options: InstanceOptions,
+    ) -> Result<Self, Error> {
+        let (gpu, adapter, _) = adapter::open(&options, None).await?;
 
-        let image_dimensions = particle_rgba.dimensions();
-        let image_size = wgpu::Extent3d {
-            width: image_dimensions.0,
-            height: image_dimensions.1,
-            depth_or_array_layers: 1,
-        };
+        let (device, queue) = Self::request_device(&adapter).await?;
 
-        let particle_texture = device.create_texture(&TextureDescriptor {
-            size: image_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            label: Some("particle_texture"),
-            view_formats: &[],
-        });
-
-        queue.write_texture(
-            ImageCopyTextureBase {
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-                texture: &particle_texture,
-            },
-            &particle_rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * image_dimensions.0),
-                rows_per_image: Some(image_dimensions.1),
-            },
-            image_size,
-        );
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
 
-        let particle_view = particle_texture.create_view(&TextureViewDescriptor::default());
-        let particle_sampler = device.create_sampler(&SamplerDescriptor {
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
-            address_mode_w: AddressMode::ClampToEdge,
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Nearest,
-            mipmap_filter: FilterMode::Nearest,
-            ..Default::default()
-        });
+        Self::with_device(gpu, adapter, device, queue, config, options.output_dir)
+    }
 
-        let particle_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("Particle Bind Group Layout"),
-                entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture {
-                            sample_type: TextureSampleType::Float { filterable: true },
-                            view_dimension: TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
-        let particle_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Particle Bind Group"),
-            layout: &particle_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&particle_view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&particle_sampler),
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    // Timestamps are only used for profiling, go without them when unsupported
+                    required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+                    required_limits: Self::required_limits(adapter),
+                    label: None,
+                    memory_hints: Default::default(),
                 },
-            ],
-        });
+                None,
+            )
+            .await
+    }
 
-        let camera = Camera::new(size, &device);
+    /// The default limits, or the downlevel ones on adapters short of them, raised to
+    /// the storage buffers the engine binds. Per-particle attributes push the particle
+    /// buffer past the default binding size, so that goes as high as the adapter allows.
+    fn required_limits(adapter: &wgpu::Adapter) -> wgpu::Limits {
+        let supported = adapter.limits();
+        let base = if wgpu::Limits::default().check_limits(&supported) {
+            wgpu::Limits::default()
+        } else {
+            wgpu::Limits::downlevel_defaults()
+        };
+        wgpu::Limits {
+            max_storage_buffers_per_shader_stage: base
+                .max_storage_buffers_per_shader_stage
+                .max(STORAGE_BUFFERS_PER_STAGE),
+            max_storage_buffer_binding_size: supported.max_storage_buffer_binding_size,
+            max_buffer_size: supported.max_buffer_size,
+            ..base
+        }
+    }
 
-        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Particle Shader Layout"),
-            bind_group_layouts: &[&particle_bind_group_layout, camera.layout()],
-            push_constant_ranges: &[],
-        });
+    fn with_device(
+        gpu: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        output_dir: PathBuf,
+    ) -> Result<Self, Error> {
+        let size = PhysicalSize::new(config.width, config.height);
 
-        let particle_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("Particle Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                compilation_options: PipelineCompilationOptions::default(),
-                buffers: &[Vertex::desc(), RawParticleInstance::desc()],
-            },
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        let atlas = SpriteAtlas::builtin().map_err(|source| Error::Asset {
+            name: "particle.png",
+            source,
+        })?;
+        let sprite = ParticleSprite::new(&atlas, &device, &queue)?;
 
-        let buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("My Vertex Buffer"),
-            contents: bytemuck::cast_slice(TRIANGLE_VERTS),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        let scene = Scene::default();
+        let mut camera = Camera::new(size, &device);
+        camera.zoom = scene.camera.zoom;
 
-        let particle_compute = ParticleCompute::new(&device);
+        let particle_compute = ParticleCompute::new(&device, &scene)?;
+        let colouring = ParticleColouring::new(
+            &device,
+            &queue,
+            particle_compute.particle_buffer(),
+            particle_compute.particle_count(),
+            particle_compute.timestep(),
+        );
 
-        Self {
-            window,
-            surface,
+        let particle_pipeline = ParticlePipeline::new(
+            &device,
+            &[sprite.layout(), camera.layout(), colouring.layout()],
+            config.format,
+        );
+
+        let heatmap = Heatmap::new(
+            particle_compute.particle_buffer(),
+            particle_compute.particle_count(),
+            colouring.map(),
+            camera.layout(),
+            config.format,
+            &device,
+            &queue,
+        );
+
+        let profiler = GpuProfiler::new(&device, &queue);
+
+        Ok(Self {
+            gpu,
+            adapter,
+            window: None,
+            surface: None,
             device,
             queue,
             config,
             size,
             particle_pipeline,
+            post: None,
+            capture_post: None,
+            trails: None,
             camera,
-            vertex_buffer: buffer,
-            particle_bind_group,
+            sprite,
+            heatmap,
+            scene,
+            scene_path: None,
             particle_compute,
+            colouring,
             fps: FPSCounter::new(),
-            mouse_position : Vector::default()
-        }
+            profiler,
+            hud: None,
+            reduction: None,
+            diagnostics: None,
+            shader_watcher: None,
+            compute_source: None,
+            force_modules: Vec::new(),
+            vector_field: None,
+            shader_error: None,
+            present_modes: Vec::new(),
+            frame_limit: None,
+            next_frame: Instant::now(),
+            mouse_position : Vector::default(),
+            cursor_world: [0., 0.],
+            cursor_screen: [0., 0.],
+            panning: false,
+            step: 0,
+            recorder: None,
+            output_dir,
+        })
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
@@ -288,67 +382,404 @@ impl<'a> Instance<'a> {
         self.camera.height = new_size.height as f32;
         self.config.width = new_size.width;
         self.config.height = new_size.height;
-        self.surface.configure(&self.device, &self.config);
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+
+        if let Some(post) = &mut self.post {
+            post.resize(new_size, &self.device);
+        }
+        if let Some(trails) = &mut self.trails {
+            trails.resize(new_size, &self.device);
+        }
     }
 
     pub fn update(&mut self) {
+        let start = Instant::now();
+        self.reload_shaders();
         self.camera.update(&self.queue);
         self.particle_compute.mouse(self.mouse_position, &self.queue);
+        self.fps.add_stage_time(FrameStage::Update, start.elapsed().as_secs_f32());
     }
 
+    /// Steps the simulation and draws it to the surface.
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        let output = match &self.surface {
+            Some(surface) => Some(surface.get_current_texture()?),
+            None => None,
+        };
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let view = output.as_ref().map(|output| {
+            output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
 
+        let encode_start = Instant::now();
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
-        self.particle_compute.compute(&mut encoder);
-        
-        {
-            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: wgpu::LoadOp::Clear(Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
+        if let Some(times) = self.profiler.as_mut().and_then(|p| p.poll(&self.device)) {
+            self.fps.add_gpu_times(times);
+        }
+        self.particle_compute.poll(&self.device);
+        self.fps.add_timestep(self.particle_compute.last_timestep());
+        if let Some(reduction) = &mut self.reduction {
+            for latest in reduction.poll(&self.device) {
+                let initial = self.diagnostics.map_or(latest, |(initial, _)| initial);
+                self.diagnostics = Some((initial, latest));
+            }
+        }
+
+        let profiler = self.profiler.as_ref();
+        self.particle_compute.compute(&mut encoder, profiler);
+        self.colouring.compute(&mut encoder, profiler);
+        if self.render_mode() == RenderMode::Heatmap {
+            self.heatmap.compute(&mut encoder, profiler);
+        }
+        self.particle_compute.copy_clock(&mut encoder);
+        if let Some(reduction) = &mut self.reduction {
+            reduction.compute(&mut encoder, self.step + 1);
+        }
+
+        let clear_trails = self.trails.as_mut().is_some_and(Trails::take_stale);
+
+        if view.is_some() && self.hud.is_some() {
+            let lines = self.hud_lines();
+            let frametimes: Vec<f32> = self.fps.history().map(|s| s.frametime).collect();
+            if let Some(hud) = &mut self.hud {
+                hud.prepare(&lines, &frametimes, self.size, &self.queue);
+            }
+        }
+        // Drawn at the end of whichever pass writes the surface last
+        let hud = view.as_ref().and(self.hud.as_ref());
+        let direct = self.post.is_none() && self.trails.is_none();
+
+        let target = match (&self.post, &self.trails) {
+            (Some(post), _) => Some(post.hdr_view()),
+            (None, Some(trails)) => trails.accumulation_view().or(view.as_ref()),
+            (None, None) => view.as_ref(),
+        };
+
+        // Headless without HDR or trails has nothing to keep the frame in
+        if let Some(target) = target {
+            let load = match &self.trails {
+                Some(trails) => {
+                    trails.fade(&mut encoder, target, BACKGROUND, clear_trails);
+                    wgpu::LoadOp::Load
+                }
+                None => wgpu::LoadOp::Clear(BACKGROUND),
+            };
+
+            let mut pass = self.draw_particles(&mut encoder, target, load);
+            if let (Some(hud), true) = (hud, direct) {
+                hud.draw(&mut pass);
+            }
+        }
+
+        if let Some(view) = &view {
+            if let (Some(mut pass), Some(hud)) = (self.composite(&mut encoder, view), hud) {
+                hud.draw(&mut pass);
+            }
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.resolve(&mut encoder);
+        }
+        let commands = encoder.finish();
+        self.fps.add_stage_time(FrameStage::Encode, encode_start.elapsed().as_secs_f32());
+
+        let submit_start = Instant::now();
+        self.queue.submit(std::iter::once(commands));
+        if let Some(output) = output {
+            output.present();
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.map();
+        }
+        self.particle_compute.map();
+        if let Some(reduction) = &mut self.reduction {
+            reduction.map();
+        }
+        self.fps.add_stage_time(FrameStage::Submit, submit_start.elapsed().as_secs_f32());
+
+        self.step += 1;
+        self.capture_recording();
+
+        // Late frames push the schedule back rather than being made up for with a burst
+        if let Some(limit) = self.frame_limit {
+            let interval = Duration::from_secs_f32(1. / limit);
+            self.next_frame = (self.next_frame + interval).max(Instant::now());
+        }
+
+        Ok(())
+    }
+
+    /// Switches how frames are presented without recreating the surface, falling back to a
+    /// supported mode: Immediate to Mailbox, and anything to Fifo. Returns the mode used.
+    pub fn set_present_mode(&mut self, mode: PresentMode) -> PresentMode {
+        let Some(surface) = &self.surface else {
+            self.config.present_mode = mode;
+            return mode;
+        };
+
+        self.config.present_mode = supported_present_mode(mode, &self.present_modes);
+        surface.configure(&self.device, &self.config);
+        self.config.present_mode
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.config.present_mode
+    }
+
+    /// Keeps frames at least `1 / fps` seconds apart, `None` draws as fast as presenting allows.
+    /// The window's event loop waits for [`Instance::frame_deadline`] before redrawing.
+    pub fn set_frame_limit(&mut self, fps: Option<f32>) {
+        self.frame_limit = fps.filter(|fps| *fps > 0.);
+        self.next_frame = Instant::now();
+    }
+
+    pub fn frame_limit(&self) -> Option<f32> {
+        self.frame_limit
+    }
+
+    /// When the next frame may start under the frame limit, `None` without one.
+    pub fn frame_deadline(&self) -> Option<Instant> {
+        self.frame_limit.map(|_| self.next_frame)
+    }
+
+    fn capture_recording(&mut self) {
+        let Some(mut recorder) = self.recorder.take() else {
+            return;
+        };
+
+        if recorder.wants(self.step) {
+            let recorded = match self.screenshot(recorder.size()) {
+                Ok(frame) => recorder
+                    .record(frame, self.particle_compute.last_timestep())
+                    .map_err(Error::from),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = recorded {
+                log::error!("Stopped recording: {}", e);
+                recorder.finish();
+                return;
+            }
+        }
+
+        self.recorder = Some(recorder);
+    }
+
+    /// Starts capturing frames with `recorder`, finishing any previous recording.
+    pub fn start_recording(&mut self, recorder: Recorder) {
+        self.stop_recording();
+        self.recorder = Some(recorder);
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish();
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Number of simulation steps taken so far.
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    /// Draws the particles into `target`, returning the pass still open.
+    fn draw_particles<'e>(
+        &'e self,
+        encoder: &'e mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<Color>,
+    ) -> wgpu::RenderPass<'e> {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: self.profiler.as_ref().map(|p| p.render_writes(GpuPass::Draw)),
+        });
+
+        render_pass.set_bind_group(1, self.camera.group(), &[]);
+        if self.render_mode() == RenderMode::Heatmap {
+            self.heatmap.draw(&mut render_pass);
+            return render_pass;
+        }
+
+        render_pass.set_bind_group(0, self.sprite.group(), &[]);
+        render_pass.set_bind_group(2, self.colouring.group(), &[]);
+
+        self.particle_pipeline.draw(
+            &mut render_pass,
+            self.particle_compute.get_particle_buffer(),
+            self.colouring.get_scalar_buffer(),
+            self.particle_compute.particle_count(),
+        );
+        render_pass
+    }
+
+    fn hud_lines(&self) -> Vec<String> {
+        let stats = self.fps.stats();
+        let work = match self.fps.gpu_stats() {
+            Some(gpu) => format!("GPU {:.2} MS", gpu.mean * 1000.),
+            None => format!("CPU {:.2} MS", self.fps.cpu_stats().mean * 1000.),
+        };
+
+        let mut lines = vec![
+            format!(
+                "FPS {:.0}  {:.2} MS MEDIAN  {:.2} MS P99",
+                stats.fps(),
+                stats.median * 1000.,
+                stats.p99 * 1000.
+            ),
+            format!(
+                "{}  {:?}{}",
+                work,
+                self.present_mode(),
+                self.frame_limit
+                    .map_or(String::new(), |fps| format!("  LIMIT {} FPS", fps))
+            ),
+            format!("PARTICLES {}", self.particle_compute.particle_count()),
+            format!(
+                "STEP {}  TIME {:.2} S  DT {:.3} MS{}  {:?}",
+                self.step,
+                self.particle_compute.time(),
+                self.particle_compute.last_timestep() * 1000.,
+                if self.adaptive_timestep().is_some() { " ADAPTIVE" } else { "" },
+                self.integrator()
+            ),
+            format!(
+                "CAMERA {:.1}, {:.1}  ZOOM {:.2}",
+                self.camera.eye.x, self.camera.eye.y, self.camera.zoom
+            ),
+            format!("CURSOR {:.1}, {:.1}", self.cursor_world[0], self.cursor_world[1]),
+        ];
+
+        if let Some((initial, latest)) = self.diagnostics {
+            let drift = (latest.total_energy() - initial.total_energy())
+                / initial.total_energy().abs().max(f32::MIN_POSITIVE);
+            lines.extend([
+                format!(
+                    "ENERGY {:.4E}  KINETIC {:.4E}  DRIFT {:+.3}%",
+                    latest.total_energy(),
+                    latest.kinetic_energy,
+                    drift * 100.
+                ),
+                format!(
+                    "MOMENTUM {:.3E}, {:.3E}  ANGULAR {:.4E}",
+                    latest.momentum[0], latest.momentum[1], latest.angular_momentum
+                ),
+                format!(
+                    "CENTRE OF MASS {:.1}, {:.1}",
+                    latest.centre_of_mass[0], latest.centre_of_mass[1]
+                ),
+                format!(
+                    "BOUNDS {:.0}, {:.0} TO {:.0}, {:.0}",
+                    latest.bounds_min[0],
+                    latest.bounds_min[1],
+                    latest.bounds_max[0],
+                    latest.bounds_max[1]
+                ),
+            ]);
+        }
+
+        if let Some(error) = &self.shader_error {
+            lines.push("SHADER ERROR".to_string());
+            lines.extend(error.lines().filter(|l| !l.trim().is_empty()).take(4).map(str::to_string));
+        }
+        lines
+    }
+
+    /// Resolves the HDR or trail target onto `output`, returning the pass that wrote it.
+    /// Does nothing when drawing directly.
+    fn composite<'e>(
+        &self,
+        encoder: &'e mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+    ) -> Option<wgpu::RenderPass<'e>> {
+        if let Some(post) = &self.post {
+            Some(post.apply(encoder, output))
+        } else if let Some(trails) = &self.trails {
+            trails.composite(encoder, output)
+        } else {
+            None
+        }
+    }
+
+    /// Draws the current state of the simulation into an offscreen texture of any size
+    /// and reads it back, without stepping the simulation.
+    ///
+    /// Trails only exist at the size of the window, with them on the screenshot has to be
+    /// that size too.
+    pub fn screenshot(&mut self, size: PhysicalSize<u32>) -> Result<RgbaImage, Error> {
+        if self.trails.is_some() && size != self.size {
+            return Err(Error::CaptureSize {
+                requested: size,
+                available: self.size,
             });
+        }
 
-            render_pass.set_pipeline(&self.particle_pipeline);
+        let target = OffscreenTarget::new(size, self.config.format, &self.device)?;
 
-            render_pass.set_bind_group(0, &self.particle_bind_group, &[]);
-            render_pass.set_bind_group(1, self.camera.group(), &[]);
+        self.camera.width = size.width as f32;
+        self.camera.height = size.height as f32;
+        self.camera.update(&self.queue);
 
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.particle_compute.get_particle_buffer());
-            render_pass.draw(
-                0..(TRIANGLE_VERTS.len() as u32),
-                0..self.particle_compute.particle_count() as _,
-            );
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Screenshot Encoder"),
+            });
+
+        if self.trails.is_some() {
+            self.composite(&mut encoder, target.view());
+        } else if let Some(settings) = self.hdr() {
+            let post = match self.capture_post.take() {
+                Some((post_size, mut post)) if post_size == size => {
+                    post.set_settings(settings, &self.queue);
+                    post
+                }
+                _ => PostProcess::new(settings, size, self.config.format, &self.device),
+            };
+            self.draw_particles(&mut encoder, post.hdr_view(), wgpu::LoadOp::Clear(BACKGROUND));
+            post.apply(&mut encoder, target.view());
+            self.capture_post = Some((size, post));
+        } else {
+            self.draw_particles(&mut encoder, target.view(), wgpu::LoadOp::Clear(BACKGROUND));
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
 
-        Ok(())
+        self.camera.width = self.size.width as f32;
+        self.camera.height = self.size.height as f32;
+        self.camera.update(&self.queue);
+
+        target.read(&self.device, &self.queue)
+    }
+
+    /// Saves a screenshot as a PNG, see [`Instance::screenshot`].
+    pub fn save_screenshot(
+        &mut self,
+        path: impl AsRef<Path>,
+        size: PhysicalSize<u32>,
+    ) -> Result<(), Error> {
+        Ok(self.screenshot(size)?.save_with_format(path, image::ImageFormat::Png)?)
     }
 
     pub fn frametime(&mut self, ft: f32) {
@@ -359,6 +790,408 @@ impl<'a> Instance<'a> {
         self.fps.get_fps() as f32
     }
 
+    /// Frame time statistics over the last [`FPSCounter::window`] frames.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.fps.stats()
+    }
+
+    /// The frame time history, including the CPU time spent updating, encoding and submitting.
+    pub fn fps(&self) -> &FPSCounter {
+        &self.fps
+    }
+
+    /// Times the compute and draw passes on the GPU, see [`FPSCounter::gpu_stats`].
+    /// Returns whether profiling is on, which needs timestamp query support.
+    pub fn set_gpu_profiling(&mut self, enabled: bool) -> bool {
+        if enabled == self.profiler.is_some() {
+            return enabled;
+        }
+
+        self.profiler = match enabled {
+            true => GpuProfiler::new(&self.device, &self.queue),
+            false => None,
+        };
+        self.profiler.is_some()
+    }
+
+    pub fn gpu_profiling(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Shows frame statistics, simulation and camera state over the frame.
+    pub fn set_hud(&mut self, enabled: bool) {
+        self.hud = match enabled {
+            true => self.hud.take().or_else(|| Some(Hud::new(self.config.format, &self.device))),
+            false => None,
+        };
+    }
+
+    pub fn hud(&self) -> bool {
+        self.hud.is_some()
+    }
+
+    /// Sums energy, momentum and the extent of the particles every step, see [`Diagnostics`].
+    pub fn set_diagnostics(&mut self, enabled: bool) {
+        if enabled == self.reduction.is_some() {
+            return;
+        }
+
+        self.reduction = enabled.then(|| self.create_reduction());
+        self.diagnostics = None;
+    }
+
+    /// Watches the shaders in the source tree, swapping them in whenever they are saved.
+    /// Shaders that fail to compile are logged and shown in the HUD, the old ones stay in use.
+    pub fn set_shader_hot_reload(&mut self, enabled: bool) {
+        if enabled == self.shader_watcher.is_some() {
+            return;
+        }
+
+        self.shader_watcher = enabled.then(|| ShaderWatcher::new(SHADER_DIR));
+        self.shader_error = None;
+    }
+
+    pub fn shader_hot_reload(&self) -> bool {
+        self.shader_watcher.is_some()
+    }
+
+    /// Why the last reloaded shader was rejected, `None` once one compiles.
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader_error.as_deref()
+    }
+
+    /// Replaces one of the engine's shaders with `source`, keeping the particles.
+    pub fn set_shader(&mut self, file: ShaderFile, source: &str) -> Result<(), String> {
+        match file {
+            ShaderFile::ParticleCompute => {
+                self.particle_compute
+                    .set_shader(source, &self.force_modules, &self.device)?;
+                self.compute_source = Some(source.to_string());
+            }
+            ShaderFile::ParticleShader => {
+                shaders::validate(source, file)?;
+                self.particle_pipeline.set_shader(source, &self.device)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `module` to the forces acting on every particle, without restarting the scene.
+    /// When the module doesn't compile it isn't added and the error is returned.
+    pub fn add_force_module(&mut self, module: ForceModule) -> Result<(), String> {
+        self.force_modules.push(module);
+        let result = self.apply_compute_shader();
+        if result.is_err() {
+            self.force_modules.pop();
+        }
+        result
+    }
+
+    /// Removes the force module called `name`, returns false when there is none.
+    pub fn remove_force_module(&mut self, name: &str) -> bool {
+        let count = self.force_modules.len();
+        self.force_modules.retain(|module| module.name() != name);
+        if self.force_modules.len() == count {
+            return false;
+        }
+
+        if let Err(e) = self.apply_compute_shader() {
+            log::error!("Failed to rebuild the compute shader: {}", e);
+        }
+        true
+    }
+
+    /// Updates the params of the force module called `name`, returns false when there is
+    /// no such module.
+    pub fn set_force_params<T: Pod>(&mut self, name: &str, params: &T) -> bool {
+        let Some(module) = self.force_modules.iter_mut().find(|m| m.name() == name) else {
+            return false;
+        };
+
+        module.set_params(params);
+        let padded = module.padded_params().unwrap_or_default();
+        // Params the shader doesn't read have nothing to write to
+        self.particle_compute
+            .write_force_params(name, &padded, &self.queue);
+        true
+    }
+
+    pub fn force_modules(&self) -> &[ForceModule] {
+        &self.force_modules
+    }
+
+    /// Adds the forces of `field` to those acting on every particle, `None` removes it.
+    /// Call again with a new field to update it, fields of the same size are cheap to upload.
+    pub fn set_vector_field(&mut self, field: Option<VectorField>) {
+        self.particle_compute
+            .set_field(field.as_ref(), &self.device, &self.queue);
+        self.vector_field = field;
+    }
+
+    pub fn vector_field(&self) -> Option<&VectorField> {
+        self.vector_field.as_ref()
+    }
+
+    /// Rebuilds the compute shader from the last reloaded source and the force modules.
+    fn apply_compute_shader(&mut self) -> Result<(), String> {
+        let source = self.compute_source.as_deref().unwrap_or(compute::SHADER);
+        self.particle_compute
+            .set_shader(source, &self.force_modules, &self.device)
+    }
+
+    fn reload_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+
+        for (file, source) in watcher.poll() {
+            match self.set_shader(file, &source) {
+                Ok(_) => {
+                    log::info!("Reloaded {}", file.file_name());
+                    self.shader_error = None;
+                }
+                Err(e) => {
+                    log::error!("Failed to reload {}:\n{}", file.file_name(), e);
+                    self.shader_error = Some(format!("{}: {}", file.file_name(), e));
+                }
+            }
+        }
+    }
+
+    fn create_reduction(&self) -> Reduction {
+        Reduction::new(&self.particle_compute, &self.device)
+    }
+
+    /// The latest diagnostics read back, a frame or two behind the simulation.
+    pub fn diagnostics(&self) -> Option<Diagnostics> {
+        self.diagnostics.map(|(_, latest)| latest)
+    }
+
+    /// The first diagnostics read back since the scene started, to measure drift against.
+    pub fn initial_diagnostics(&self) -> Option<Diagnostics> {
+        self.diagnostics.map(|(initial, _)| initial)
+    }
+
+    /// Number of frames the statistics and history are kept over.
+    pub fn set_stats_window(&mut self, frames: usize) {
+        self.fps.set_window(frames);
+    }
+
+    /// Saves the frame time history as CSV, see [`FPSCounter::write_csv`].
+    pub fn save_frame_history(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.fps.write_csv(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn set_colouring(&mut self, mode: ColourMode, range: ColourRange) {
+        self.colouring.set_mode(mode, range, &self.queue);
+    }
+
+    pub fn colouring(&self) -> (ColourMode, ColourRange) {
+        (self.colouring.mode(), self.colouring.range())
+    }
+
+    pub fn set_colour_map(&mut self, map: ColourMap) {
+        self.heatmap.set_map(&map, &self.device, &self.queue);
+        self.colouring.set_map(map, &self.device, &self.queue);
+    }
+
+    /// Whether [`RenderMode::Heatmap`] counts particles or sums their mass.
+    pub fn set_heatmap_weight(&mut self, weight: HeatmapWeight) {
+        self.heatmap.set_weight(weight, &self.queue);
+    }
+
+    /// Sets the per-particle scalars shown by [`ColourMode::Custom`], in particle order.
+    /// Fails unless there is exactly one for each particle.
+    pub fn set_custom_scalars(&mut self, scalars: &[f32]) -> Result<(), Error> {
+        self.colouring.set_custom_scalars(scalars, &self.queue)
+    }
+
+    /// Restarts the simulation from `scene`, replacing the particles, forces and camera.
+    /// When the scene is invalid or too big for the device the current one keeps running.
+    pub fn set_scene(&mut self, scene: Scene) -> Result<(), Error> {
+        scene.validate()?;
+        self.particle_compute = ParticleCompute::new(&self.device, &scene)?;
+        if self.compute_source.is_some() || !self.force_modules.is_empty() {
+            if let Err(e) = self.apply_compute_shader() {
+                log::error!("Failed to rebuild the compute shader: {}", e);
+            }
+        }
+        if let Some(field) = &self.vector_field {
+            self.particle_compute
+                .set_field(Some(field), &self.device, &self.queue);
+        }
+        let count = self.particle_compute.particle_count();
+
+        // The colouring and heatmap hold on to the old particle buffer
+        let (mode, range) = self.colouring();
+        let map = self.colouring.map().clone();
+        self.colouring = ParticleColouring::new(
+            &self.device,
+            &self.queue,
+            self.particle_compute.particle_buffer(),
+            count,
+            self.particle_compute.timestep(),
+        );
+        self.colouring.set_mode(mode, range, &self.queue);
+        self.colouring.set_map(map, &self.device, &self.queue);
+
+        let weight = self.heatmap.weight();
+        self.heatmap = Heatmap::new(
+            self.particle_compute.particle_buffer(),
+            count,
+            self.colouring.map(),
+            self.camera.layout(),
+            self.config.format,
+            &self.device,
+            &self.queue,
+        );
+        self.heatmap.set_weight(weight, &self.queue);
+        let (format, blend) = self.particle_target();
+        self.heatmap.set_target(format, blend, &self.device);
+
+        let [x, y] = scene.camera.position;
+        self.camera.eye = Vector::new3(x, y, -2.);
+        self.camera.zoom = scene.camera.zoom;
+
+        // Clears the trails of the old scene
+        if let Some(trails) = &mut self.trails {
+            trails.resize(self.size, &self.device);
+        }
+
+        self.step = 0;
+        self.scene = scene;
+
+        if self.reduction.is_some() {
+            self.reduction = Some(self.create_reduction());
+            self.diagnostics = None;
+        }
+        Ok(())
+    }
+
+    /// Loads a scene file, see [`Scene`] for the format.
+    pub fn load_scene(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let scene = Scene::load(&path)?;
+        self.set_scene(scene)?;
+        self.scene_path = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    /// Reads the scene file again, or restarts the current scene when it wasn't loaded from one.
+    pub fn reload_scene(&mut self) -> Result<(), Error> {
+        match self.scene_path.clone() {
+            Some(path) => self.load_scene(path),
+            None => self.set_scene(self.scene.clone()),
+        }
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Restarts the scene with `integrator`, so runs with different ones can be compared.
+    pub fn set_integrator(&mut self, integrator: Integrator) -> Result<(), Error> {
+        let mut scene = self.scene.clone();
+        scene.simulation.integrator = integrator;
+        self.set_scene(scene)
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.scene.simulation.integrator
+    }
+
+    /// Switches adaptive timestepping without restarting, `None` goes back to the fixed timestep.
+    pub fn set_adaptive_timestep(&mut self, adaptive: Option<AdaptiveTimestep>) {
+        self.scene.simulation.adaptive = adaptive;
+        self.particle_compute.set_adaptive(adaptive, &self.queue);
+    }
+
+    pub fn adaptive_timestep(&self) -> Option<AdaptiveTimestep> {
+        self.scene.simulation.adaptive
+    }
+
+    /// Replaces the particle sprite, see [`SpriteAtlas`] for how frames are picked.
+    /// Fails when the atlas is larger than the device can hold as a texture.
+    pub fn set_sprite_atlas(&mut self, atlas: &SpriteAtlas) -> Result<(), Error> {
+        self.sprite.set_atlas(atlas, &self.device, &self.queue)
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.particle_pipeline.set_mode(mode, &self.device);
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.particle_pipeline.mode()
+    }
+
+    /// Switches to additive HDR rendering with tonemapping, `None` goes back to drawing
+    /// straight onto the surface.
+    pub fn set_hdr(&mut self, settings: Option<HdrSettings>) {
+        match (settings, &mut self.post) {
+            (Some(settings), Some(post)) => post.set_settings(settings, &self.queue),
+            (Some(settings), None) => {
+                self.post = Some(PostProcess::new(settings, self.size, self.config.format, &self.device));
+                self.retarget();
+                if let Some(trails) = &mut self.trails {
+                    trails.set_hdr(Some(HDR_FORMAT), self.size, &self.device);
+                }
+            }
+            (None, _) => {
+                self.post = None;
+                self.capture_post = None;
+                self.retarget();
+                if let Some(trails) = &mut self.trails {
+                    trails.set_hdr(None, self.size, &self.device);
+                }
+            }
+        }
+    }
+
+    /// Leaves trails behind particles, each frame keeps `decay` of the previous one.
+    /// `None` clears the screen every frame.
+    pub fn set_trails(&mut self, decay: Option<f32>) {
+        match (decay, &mut self.trails) {
+            (Some(decay), Some(trails)) => trails.set_decay(decay),
+            (Some(decay), None) => {
+                let mut trails =
+                    Trails::new(decay, BACKGROUND, self.size, self.config.format, &self.device);
+                if self.post.is_some() {
+                    trails.set_hdr(Some(HDR_FORMAT), self.size, &self.device);
+                }
+                self.trails = Some(trails);
+                self.retarget();
+            }
+            (None, _) => {
+                self.trails = None;
+                self.retarget();
+            }
+        }
+    }
+
+    /// The format and blending particles are drawn with, HDR and trails draw offscreen.
+    fn particle_target(&self) -> (wgpu::TextureFormat, wgpu::BlendState) {
+        match (&self.post, &self.trails) {
+            (Some(_), _) => (HDR_FORMAT, ADDITIVE_BLENDING),
+            (None, Some(_)) => (TRAIL_FORMAT, wgpu::BlendState::ALPHA_BLENDING),
+            (None, None) => (self.config.format, wgpu::BlendState::ALPHA_BLENDING),
+        }
+    }
+
+    /// Points the particle and heatmap pipelines at the current [`Instance::particle_target`].
+    fn retarget(&mut self) {
+        let (format, blend) = self.particle_target();
+        self.particle_pipeline
+            .set_target(format, blend, &self.device);
+        self.heatmap.set_target(format, blend, &self.device);
+    }
+
+    pub fn trails(&self) -> Option<f32> {
+        self.trails.as_ref().map(Trails::decay)
+    }
+
+    pub fn hdr(&self) -> Option<HdrSettings> {
+        self.post.as_ref().map(PostProcess::settings)
+    }
+
     pub fn reconfig(&mut self) {
         self.resize(self.size);
     }
@@ -369,13 +1202,283 @@ impl<'a> Instance<'a> {
             {
                 self.mouse_position.x = position.x as f32;
                 self.mouse_position.y = SIDE_LENGTH as f32 - position.y as f32;
+                let [x, y] = [position.x as f32, position.y as f32];
+                if self.panning {
+                    self.camera.pan(x - self.cursor_screen[0], y - self.cursor_screen[1]);
+                }
+                self.cursor_screen = [x, y];
+                self.cursor_world = self.camera.screen_to_world(x, y);
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let notches = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.,
+                };
+                let [x, y] = self.cursor_screen;
+                self.camera.zoom_at(ZOOM_STEP.powf(notches), x, y);
+                self.cursor_world = self.camera.screen_to_world(x, y);
+                return true;
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right | MouseButton::Middle,
+                ..
+            } => {
+                self.panning = *state == ElementState::Pressed;
+                return true;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(key),
+                        ..
+                    },
+                ..
+            } => match key {
+                KeyCode::Home => {
+                    let [x, y] = self.scene.camera.position;
+                    self.camera.eye = Vector::new3(x, y, -2.);
+                    self.camera.zoom = self.scene.camera.zoom;
+                    return true;
+                }
+                KeyCode::KeyC => {
+                    let mode = self.colouring.mode().next();
+                    self.colouring.set_mode(mode, ColourRange::Auto, &self.queue);
+                    log::info!("Colouring particles by {:?}", mode);
+                    return true;
+                }
+                KeyCode::KeyV => {
+                    let mode = match self.present_mode() {
+                        PresentMode::Fifo => PresentMode::Mailbox,
+                        PresentMode::Mailbox => PresentMode::Immediate,
+                        _ => PresentMode::Fifo,
+                    };
+                    let mode = self.set_present_mode(mode);
+                    log::info!("Presenting with {:?}", mode);
+                    return true;
+                }
+                KeyCode::KeyL => {
+                    let limit = match self.frame_limit() {
+                        None => Some(30.),
+                        Some(fps) if fps < 60. => Some(60.),
+                        Some(fps) if fps < 120. => Some(120.),
+                        Some(_) => None,
+                    };
+                    match limit {
+                        Some(fps) => log::info!("Limiting to {} fps", fps),
+                        None => log::info!("Frame limit off"),
+                    }
+                    self.set_frame_limit(limit);
+                    return true;
+                }
+                KeyCode::KeyR => {
+                    let mode = self.particle_pipeline.mode().next();
+                    log::info!("Drawing particles as {:?}", mode);
+                    self.particle_pipeline.set_mode(mode, &self.device);
+                    return true;
+                }
+                KeyCode::KeyH => {
+                    let settings = match self.hdr() {
+                        Some(_) => None,
+                        None => Some(HdrSettings::default()),
+                    };
+                    log::info!("HDR rendering {}", if settings.is_some() { "on" } else { "off" });
+                    self.set_hdr(settings);
+                    return true;
+                }
+                KeyCode::KeyB => {
+                    if let Some(mut settings) = self.hdr() {
+                        settings.bloom = !settings.bloom;
+                        self.set_hdr(Some(settings));
+                    }
+                    return true;
+                }
+                KeyCode::KeyT => {
+                    let decay = match self.trails() {
+                        Some(_) => None,
+                        None => Some(0.95),
+                    };
+                    log::info!("Trails {}", if decay.is_some() { "on" } else { "off" });
+                    self.set_trails(decay);
+                    return true;
+                }
+                KeyCode::F10 => {
+                    if self.is_recording() {
+                        self.stop_recording();
+                        return true;
+                    }
+
+                    let path = self.output_path(format!("recording-{}", timestamp()));
+                    match Recorder::png_sequence(&path, self.size, 1) {
+                        Ok(recorder) => {
+                            log::info!("Recording to {}", path.display());
+                            self.start_recording(recorder);
+                        }
+                        Err(e) => {
+                            log::error!("Failed to start recording to {}: {}", path.display(), e)
+                        }
+                    }
+                    return true;
+                }
+                KeyCode::F3 => {
+                    self.set_hud(self.hud.is_none());
+                    return true;
+                }
+                KeyCode::F4 => {
+                    let enabled = self.reduction.is_none();
+                    log::info!("Diagnostics {}", if enabled { "on" } else { "off" });
+                    self.set_diagnostics(enabled);
+                    return true;
+                }
+                KeyCode::F5 => {
+                    match self.reload_scene() {
+                        Ok(_) => log::info!("Reloaded the scene"),
+                        Err(e) => log::error!("{}", e),
+                    }
+                    return true;
+                }
+                KeyCode::F6 => {
+                    let enabled = !self.shader_hot_reload();
+                    log::info!("Shader hot reload {}", if enabled { "on" } else { "off" });
+                    self.set_shader_hot_reload(enabled);
+                    return true;
+                }
+                KeyCode::F9 => {
+                    let path = self.output_path(format!("frametimes-{}.csv", timestamp()));
+                    match self.save_frame_history(&path) {
+                        Ok(_) => log::info!("Saved {}", path.display()),
+                        Err(e) => log::error!("Failed to save {}: {}", path.display(), e),
+                    }
+                    return true;
+                }
+                KeyCode::F12 => {
+                    let path = self.output_path(format!("screenshot-{}.png", timestamp()));
+                    match self.save_screenshot(&path, self.size) {
+                        Ok(_) => log::info!("Saved {}", path.display()),
+                        Err(e) => log::error!("Failed to save {}: {}", path.display(), e),
+                    }
+                    return true;
+                }
+                KeyCode::KeyA => {
+                    let adaptive = match self.adaptive_timestep() {
+                        Some(_) => None,
+                        None => Some(AdaptiveTimestep::default()),
+                    };
+                    log::info!("Adaptive timestep {}", if adaptive.is_some() { "on" } else { "off" });
+                    self.set_adaptive_timestep(adaptive);
+                    return true;
+                }
+                KeyCode::KeyI => {
+                    let integrator = self.integrator().next();
+                    log::info!("Restarting with the {:?} integrator", integrator);
+                    if let Err(e) = self.set_integrator(integrator) {
+                        log::error!("{}", e);
+                    }
+                    return true;
+                }
+                KeyCode::KeyM => {
+                    let map = self.colouring.map().next();
+                    log::info!("Using the {:?} colour map", map);
+                    self.set_colour_map(map);
+                    return true;
+                }
+                _ => {}
+            },
             _ => {}
         }
         false
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    pub fn window(&self) -> Option<&Window> {
+        self.window.as_deref()
+    }
+
+    /// Starts presenting to `window`, replacing any surface already attached. Meant for
+    /// when the application resumes, or to move the simulation to another window.
+    ///
+    /// The window has to be presentable by the adapter the instance was created on. When
+    /// it prefers another format everything drawing to it is rebuilt, keeping its settings.
+    pub fn attach_surface(&mut self, window: Arc<Window>) -> Result<(), Error> {
+        let surface = self.gpu.create_surface(window.clone())?;
+        if !self.adapter.is_surface_supported(&surface) {
+            return Err(Error::SurfaceUnsupported);
+        }
+
+        let caps = surface.get_capabilities(&self.adapter);
+        let format = if caps.formats.contains(&self.config.format) {
+            self.config.format
+        } else {
+            caps.formats
+                .iter()
+                .find(|f| f.is_srgb())
+                .copied()
+                .unwrap_or(caps.formats[0])
+        };
+        self.set_format(format);
+        self.config.present_mode =
+            supported_present_mode(self.config.present_mode, &caps.present_modes);
+        self.config.alpha_mode = caps.alpha_modes[0];
+        self.present_modes = caps.present_modes;
+
+        self.surface = Some(surface);
+        self.window = Some(window.clone());
+        // A window that is still zero sized keeps the old size until it is resized
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            self.reconfig();
+        } else {
+            self.resize(size);
+        }
+        Ok(())
+    }
+
+    /// Stops presenting and lets go of the window, returning it. The simulation carries on
+    /// headless, for when the application is suspended and its surface is destroyed.
+    pub fn detach_surface(&mut self) -> Option<Arc<Window>> {
+        self.surface = None;
+        self.present_modes.clear();
+        self.window.take()
+    }
+
+    pub fn has_surface(&self) -> bool {
+        self.surface.is_some()
+    }
+
+    /// Rebuilds the pipelines and targets that draw into the surface for `format`.
+    fn set_format(&mut self, format: wgpu::TextureFormat) {
+        if format == self.config.format {
+            return;
+        }
+        self.config.format = format;
+
+        let (hdr, trails, hud) = (self.hdr(), self.trails(), self.hud());
+        self.set_hdr(None);
+        self.set_trails(None);
+        self.set_hud(false);
+        self.set_hdr(hdr);
+        self.set_trails(trails);
+        self.set_hud(hud);
     }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    pub fn particle_count(&self) -> u32 {
+        self.particle_compute.particle_count()
+    }
+
+    /// `name` inside the output directory, see [`InstanceOptions::output_dir`].
+    pub fn output_path(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.output_dir.join(name)
+    }
+}
+
+/// Seconds since the unix epoch, used to name captured files.
+fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
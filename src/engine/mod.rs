@@ -1,16 +1,49 @@
+mod adapter;
 mod cam;
 mod instance;
 mod fps;
 mod compute;
+mod colour;
+mod sprite;
+mod pipeline;
+mod post;
+mod trails;
+mod capture;
+mod recorder;
+mod heatmap;
+mod profiler;
+mod hud;
+mod scene;
+mod diagnostics;
+mod field;
+mod forces;
+mod shaders;
+pub mod generators;
 
 use bytemuck::{Pod, Zeroable};
+pub use adapter::{describe_adapter, list_adapters, AdapterSelection};
 pub use cam::*;
+pub use colour::{ColourMap, ColourMode, ColourRange};
+pub use sprite::SpriteAtlas;
+pub use pipeline::RenderMode;
+pub use post::{HdrSettings, Tonemap};
+pub use recorder::Recorder;
+pub use heatmap::HeatmapWeight;
+pub use fps::{FPSCounter, FrameSample, FrameStage, FrameStats};
+pub use profiler::{GpuPass, GpuTimes};
+pub use diagnostics::Diagnostics;
+pub use field::VectorField;
+pub use forces::ForceModule;
+pub use shaders::{ShaderFile, ShaderWatcher, SHADER_DIR};
+pub use scene::{
+    AdaptiveTimestep, Attractor, Boundary, BoundaryMode, CameraConfig, Collider, Integrator,
+    ParticleGroup, Scene, SceneError, Shape, SimulationConfig,
+};
 pub use instance::*;
+use serde::Deserialize;
 use vecto_rs::linear::{Mat4, Vector, Vector4, VectorTrait};
 use wgpu::{vertex_attr_array, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
 
-use crate::SIDE_LENGTH;
-
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -29,17 +62,59 @@ impl Vertex {
     }
 }
 
+/// Per-particle properties that are fixed at spawn time.
+///
+/// `mass` scales the acceleration produced by forces in the physics kernel,
+/// `radius` and `colour` control how the particle is drawn. `species` and
+/// `flags` are free for the user to tag particles with. `lifetime` is the time
+/// in seconds over which the particle animates through its sprite frames,
+/// zero keeps it on the first frame.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParticleAttributes {
+    pub mass: f32,
+    pub radius: f32,
+    pub colour: [f32; 4],
+    pub species: u32,
+    pub flags: u32,
+    pub lifetime: f32,
+}
+
+impl Default for ParticleAttributes {
+    fn default() -> Self {
+        Self {
+            mass: 1.,
+            radius: 0.5,
+            colour: [1., 1., 1., 1.],
+            species: 0,
+            flags: 0,
+            lifetime: 0.,
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct ParticleInstance {
     position: Vector,
     old_position : Vector,
+    pub attributes : ParticleAttributes,
 }
 
+/// GPU layout of a particle, shared by the compute kernel and the vertex shader.
+/// Must match `Particle` in `particle_compute.wgsl`.
 #[repr(C)]
 #[derive(Pod, Zeroable, Clone, Copy, Debug)]
 pub struct RawParticleInstance {
     old_position : [f32; 2],
-    position : [f32; 2]
+    position : [f32; 2],
+    colour : [f32; 4],
+    mass : f32,
+    radius : f32,
+    species : u32,
+    flags : u32,
+    age : f32,
+    lifetime : f32,
+    _padding : [f32; 2],
 }
 
 impl ParticleInstance {
@@ -48,48 +123,60 @@ impl ParticleInstance {
         Self
         {
             position : Vector::new2(x, y),
-            old_position : Vector::new2(x, y)
+            old_position : Vector::new2(x, y),
+            attributes : ParticleAttributes::default(),
         }
     }
-    
-    pub fn update(&mut self, raw : &mut RawParticleInstance)
+
+    /// Starts the particle moving by `(dx, dy)` per step.
+    pub fn with_velocity(mut self, dx : f32, dy : f32) -> Self
     {
-        let mut velocity = self.position - self.old_position;
-        velocity.y -= 0.01;
-        self.position += velocity;
-        self.old_position = self.position - velocity;
-        if self.position.y < 0.5
-        {
-            self.old_position.y = self.position.y + velocity.y;
-            self.position.y = 0.5;
-        }
-        if self.position.x < -0.5
-        {
-            self.old_position.x += SIDE_LENGTH as f32;
-            self.position.x += SIDE_LENGTH as f32;
-        }
+        self.old_position = self.position - Vector::new2(dx, dy);
+        self
+    }
 
-        if self.position.x > SIDE_LENGTH as f32 + 0.5
-        {
-            self.old_position.x -= SIDE_LENGTH as f32;
-            self.position.x -= SIDE_LENGTH as f32;
+    pub fn with_attributes(mut self, attributes : ParticleAttributes) -> Self
+    {
+        self.attributes = attributes;
+        self
+    }
+
+    /// The default initial condition, a `side_length` by `side_length` grid of particles
+    /// falling with a small downward kick.
+    pub fn grid(side_length : usize) -> Vec<Self>
+    {
+        let instance_count = side_length * side_length;
+        let mut instances = Vec::with_capacity(instance_count);
+
+        for i in 0..instance_count {
+            instances.push(ParticleInstance::new(
+                (i / side_length) as f32 + 0.5,
+                (i % side_length) as f32 + 0.5,
+            ).with_velocity(0., -0.1));
         }
 
-        raw.position[0] = self.position.x;
-        raw.position[1] = self.position.y;
+        instances
     }
-
+    
     pub fn raw(&self) -> RawParticleInstance {
         RawParticleInstance {
             position: [self.position.x, self.position.y],
-            old_position : [self.position.x, self.position.y + 0.1],
+            old_position : [self.old_position.x, self.old_position.y],
+            colour : self.attributes.colour,
+            mass : self.attributes.mass,
+            radius : self.attributes.radius,
+            species : self.attributes.species,
+            flags : self.attributes.flags,
+            age : 0.,
+            lifetime : self.attributes.lifetime,
+            _padding : [0.; 2],
         }
     }
 }
 
 impl RawParticleInstance {
-    const ATTRIB: [VertexAttribute; 1] =
-        vertex_attr_array![5 => Float32x2];
+    const ATTRIB: [VertexAttribute; 9] =
+        vertex_attr_array![5 => Float32x2, 6 => Float32x2, 7 => Float32x4, 8 => Float32, 9 => Float32, 10 => Uint32, 11 => Uint32, 13 => Float32, 14 => Float32];
     pub fn desc() -> VertexBufferLayout<'static> {
         VertexBufferLayout {
             step_mode: VertexStepMode::Instance,
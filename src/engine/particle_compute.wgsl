@@ -2,6 +2,13 @@ struct Particle
 {
     old_position : vec2<f32>,
     position : vec2<f32>,
+    colour : vec4<f32>,
+    mass : f32,
+    radius : f32,
+    species : u32,
+    flags : u32,
+    age : f32,
+    lifetime : f32,
 }
 
 @group(0) @binding(0)
@@ -10,38 +17,380 @@ var<storage, read_write> particles : array<Particle>;
 struct Uniforms
 {
     side_length : u32,
+    count : u32,
     mouse : vec2<f32>,
+    boundary_min : vec2<f32>,
+    boundary_max : vec2<f32>,
+    // Velocities are stored as the displacement over this many seconds, the fixed
+    // timestep and the largest adaptive one
+    timestep : f32,
+    attractor_count : u32,
+    collider_count : u32,
+    boundary : u32,
+    restitution : f32,
+    adaptive : u32,
+    min_timestep : f32,
+    max_displacement : f32,
+    accuracy : f32,
+    // Whether the vector field is set, and the rectangle it covers
+    field : u32,
+    field_min : vec2<f32>,
+    field_max : vec2<f32>,
 }
 
 @group(0) @binding(1)
 var<uniform> uniforms : Uniforms;
 
-fn physics(index : u32)
+// Kept on the GPU so adaptive timesteps never wait on the CPU
+struct Clock
 {
-    var velocity : vec2<f32> = particles[index].position - particles[index].old_position;
+    time : f32,
+    // The step about to be taken
+    timestep : f32,
+    // The step taken before it
+    previous : f32,
+}
+
+@group(0) @binding(4)
+var<storage, read_write> clock : Clock;
+
+// Largest speed and acceleration of the step, as the bits of positive floats which
+// compare in the same order as the floats
+struct Limits
+{
+    speed : atomic<u32>,
+    acceleration : atomic<u32>,
+}
+
+@group(0) @binding(5)
+var<storage, read_write> limits : Limits;
+
+struct Attractor
+{
+    position : vec2<f32>,
+    velocity : vec2<f32>,
+    strength : f32,
+    falloff : f32,
+    softening : f32,
+}
+
+@group(0) @binding(2)
+var<storage, read> attractors : array<Attractor>;
+
+struct Collider
+{
+    kind : u32,
+    restitution : f32,
+    // The centre of a circle or the minimum of a box
+    a : vec2<f32>,
+    // The radius of a circle in x or the maximum of a box
+    b : vec2<f32>,
+}
+
+@group(0) @binding(3)
+var<storage, read> colliders : array<Collider>;
 
-    // F = (G * m1 * m2) / d^2
-    let center : vec2<f32> = vec2<f32>(f32(uniforms.side_length) / 2., f32(uniforms.side_length) / 2.);
-    // let center : vec2<f32> = uniforms.mouse;
-    let dist : f32 = distance(center, particles[index].position);
-    let force : f32= (9. * 1. * 100.) / (dist);
-    let acc : f32 = force / 0.1;
-    let impulse : vec2<f32> = force * (1. / 60.) * normalize(center - particles[index].position);
-     velocity += impulse;
+@group(0) @binding(6)
+var field : texture_2d<f32>;
 
+@group(0) @binding(7)
+var field_sampler : sampler;
 
-    if particles[index].position.y < 0.5
+const CIRCLE : u32 = 0u;
+const REFLECT : u32 = 1u;
+const WRAP : u32 = 2u;
+
+// Reflects the part of the velocity going into a surface with normal n
+fn bounce(velocity : vec2<f32>, n : vec2<f32>, restitution : f32) -> vec2<f32>
+{
+    let into = dot(velocity, n);
+    if into >= 0.
     {
-        velocity = velocity * -1.;
+        return velocity;
     }
+    return velocity - (1. + restitution) * into * n;
+}
 
-    particles[index].old_position = particles[index].position;
-    particles[index].position += velocity;
+// Force of the attractors at `time` on a particle at `position`
+fn attractor_force(position : vec2<f32>, time : f32) -> vec2<f32>
+{
+    var acc = vec2<f32>(0.);
+    for (var i = 0u; i < uniforms.attractor_count; i++)
+    {
+        let attractor = attractors[i];
+        let offset = attractor.position + attractor.velocity * time - position;
+        let dist = sqrt(dot(offset, offset) + attractor.softening * attractor.softening);
+        if dist > 0.
+        {
+            // strength / d^falloff along the unit offset
+            acc += offset * attractor.strength / (dist * pow(dist, attractor.falloff));
+        }
+    }
+    return acc;
+}
+
+// Force of the vector field, interpolated between the centres of its values and zero
+// outside its rectangle. The first row of the texture is at the bottom
+fn field_force(position : vec2<f32>) -> vec2<f32>
+{
+    if uniforms.field == 0u
+    {
+        return vec2<f32>(0.);
+    }
+
+    let uv = (position - uniforms.field_min) / (uniforms.field_max - uniforms.field_min);
+    if any(uv < vec2<f32>(0.)) || any(uv > vec2<f32>(1.))
+    {
+        return vec2<f32>(0.);
+    }
+    return textureSampleLevel(field, field_sampler, uv, 0.).xy;
+}
+
+fn collide(position : ptr<function, vec2<f32>>, velocity : ptr<function, vec2<f32>>)
+{
+    for (var i = 0u; i < uniforms.collider_count; i++)
+    {
+        let collider = colliders[i];
+        if collider.kind == CIRCLE
+        {
+            let offset = *position - collider.a;
+            let dist = length(offset);
+            if dist < collider.b.x && dist > 0.
+            {
+                let n = offset / dist;
+                *position = collider.a + n * collider.b.x;
+                *velocity = bounce(*velocity, n, collider.restitution);
+            }
+        }
+        else if all(*position > collider.a) && all(*position < collider.b)
+        {
+            // Push out through the nearest face
+            let to_min = *position - collider.a;
+            let to_max = collider.b - *position;
+            let nearest = min(min(to_min.x, to_max.x), min(to_min.y, to_max.y));
+            var n = vec2<f32>(0., 1.);
+            if nearest == to_min.x
+            {
+                n = vec2<f32>(-1., 0.);
+                (*position).x = collider.a.x;
+            }
+            else if nearest == to_max.x
+            {
+                n = vec2<f32>(1., 0.);
+                (*position).x = collider.b.x;
+            }
+            else if nearest == to_min.y
+            {
+                n = vec2<f32>(0., -1.);
+                (*position).y = collider.a.y;
+            }
+            else
+            {
+                (*position).y = collider.b.y;
+            }
+            *velocity = bounce(*velocity, n, collider.restitution);
+        }
+    }
+}
+
+fn boundary(position : ptr<function, vec2<f32>>, velocity : ptr<function, vec2<f32>>)
+{
+    let lower = uniforms.boundary_min;
+    let upper = uniforms.boundary_max;
+    if uniforms.boundary == REFLECT
+    {
+        if (*position).x < lower.x
+        {
+            (*position).x = lower.x;
+            *velocity = bounce(*velocity, vec2<f32>(1., 0.), uniforms.restitution);
+        }
+        if (*position).x > upper.x
+        {
+            (*position).x = upper.x;
+            *velocity = bounce(*velocity, vec2<f32>(-1., 0.), uniforms.restitution);
+        }
+        if (*position).y < lower.y
+        {
+            (*position).y = lower.y;
+            *velocity = bounce(*velocity, vec2<f32>(0., 1.), uniforms.restitution);
+        }
+        if (*position).y > upper.y
+        {
+            (*position).y = upper.y;
+            *velocity = bounce(*velocity, vec2<f32>(0., -1.), uniforms.restitution);
+        }
+    }
+    else if uniforms.boundary == WRAP
+    {
+        let size = upper - lower;
+        let offset = *position - lower;
+        *position = lower + offset - floor(offset / size) * size;
+    }
+}
+
+// Sum of the registered `ForceModule`s, which are composed in between these lines
+// force modules begin
+fn custom_force(particle : Particle, index : u32) -> vec2<f32>
+{
+    return vec2<f32>(0.);
+}
+// force modules end
+
+fn acceleration(index : u32, position : vec2<f32>, time : f32) -> vec2<f32>
+{
+    var particle = particles[index];
+    particle.position = position;
+    let total = attractor_force(position, time) + field_force(position) + custom_force(particle, index);
+    return total / particle.mass;
+}
+
+// Velocity in world units per second, stored as the displacement over `uniforms.timestep`
+// whatever the length of the last step
+fn velocity(index : u32) -> vec2<f32>
+{
+    return (particles[index].position - particles[index].old_position) / uniforms.timestep;
+}
+
+// Resolves collisions and the boundary, then stores the position and velocity.
+// `acc` is the acceleration at the start of the step, for picking the next timestep
+fn finish(index : u32, position : vec2<f32>, velocity : vec2<f32>, acc : vec2<f32>)
+{
+    var next = position;
+    var step = velocity * uniforms.timestep;
+    collide(&next, &step);
+    boundary(&next, &step);
+
+    particles[index].old_position = next - step;
+    particles[index].position = next;
+    particles[index].age += clock.timestep;
+
+    if uniforms.adaptive != 0u
+    {
+        atomicMax(&limits.speed, bitcast<u32>(length(step) / uniforms.timestep));
+        atomicMax(&limits.acceleration, bitcast<u32>(length(acc)));
+    }
+}
+
+// Position Verlet, the stored velocity is the one halfway through the last step. Between
+// steps of different lengths the kick spans the two half steps on either side
+fn verlet(index : u32)
+{
+    let dt = clock.timestep;
+    let position = particles[index].position;
+    let acc = acceleration(index, position, clock.time);
+    let half = velocity(index) + acc * (dt + clock.previous) * 0.5;
+    finish(index, position + half * dt, half, acc);
+}
+
+// Velocity Verlet as kick-drift-kick, the velocity stays in step with the position
+fn leapfrog(index : u32)
+{
+    let dt = clock.timestep;
+    let time = clock.time;
+    let position = particles[index].position;
+    let acc = acceleration(index, position, time);
+    let half = velocity(index) + acc * dt * 0.5;
+    let next = position + half * dt;
+    finish(index, next, half + acceleration(index, next, time + dt) * dt * 0.5, acc);
+}
+
+// Classic fourth order Runge-Kutta, not symplectic so energy slowly drifts
+fn rk4(index : u32)
+{
+    let dt = clock.timestep;
+    let time = clock.time;
+    let x = particles[index].position;
+    let v = velocity(index);
+
+    let k1x = v;
+    let k1v = acceleration(index, x, time);
+    let k2x = v + k1v * dt * 0.5;
+    let k2v = acceleration(index, x + k1x * dt * 0.5, time + dt * 0.5);
+    let k3x = v + k2v * dt * 0.5;
+    let k3v = acceleration(index, x + k2x * dt * 0.5, time + dt * 0.5);
+    let k4x = v + k3v * dt;
+    let k4v = acceleration(index, x + k3x * dt, time + dt);
+
+    finish(
+        index,
+        x + (k1x + 2. * k2x + 2. * k3x + k4x) * dt / 6.,
+        v + (k1v + 2. * k2v + 2. * k3v + k4v) * dt / 6.,
+        k1v,
+    );
+}
+
+fn thread_index(global_id : vec3<u32>, groups : vec3<u32>) -> u32
+{
+    return global_id.x + (global_id.y * groups.x * 64u);
+}
+
+// One entry point per integrator, see `Integrator`
+
+@compute
+@workgroup_size(64)
+fn main_verlet(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index < uniforms.count
+    {
+        verlet(index);
+    }
 }
 
+@compute
+@workgroup_size(64)
+fn main_leapfrog(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index < uniforms.count
+    {
+        leapfrog(index);
+    }
+}
+
+@compute
+@workgroup_size(64)
+fn main_rk4(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>)
+{
+    let index = thread_index(global_id, groups);
+    if index < uniforms.count
+    {
+        rk4(index);
+    }
+}
+
+// Largest factor the timestep grows by in one step, so it recovers gradually after a close pass
+const MAX_GROWTH : f32 = 2.;
+
+// Moves the clock on by the step just taken and picks the next one, run on a single thread
+// after every step
 @compute
 @workgroup_size(1)
-fn main(@builtin(global_invocation_id) global_id: vec3<u32>)
+fn advance_clock()
 {
-    physics(global_id.x + (global_id.y * uniforms.side_length));
-}
\ No newline at end of file
+    let taken = clock.timestep;
+    clock.time += taken;
+    clock.previous = taken;
+
+    if uniforms.adaptive == 0u
+    {
+        clock.timestep = uniforms.timestep;
+        return;
+    }
+
+    let speed = bitcast<f32>(atomicExchange(&limits.speed, 0u));
+    let acc = bitcast<f32>(atomicExchange(&limits.acceleration, 0u));
+
+    var next = min(uniforms.timestep, taken * MAX_GROWTH);
+    // Courant condition, nothing moves further than the displacement limit
+    if speed > 0.
+    {
+        next = min(next, uniforms.max_displacement / speed);
+    }
+    // Accuracy condition, nothing strays further than the accuracy from a straight line
+    if acc > 0.
+    {
+        next = min(next, sqrt(2. * uniforms.accuracy / acc));
+    }
+    clock.timestep = clamp(next, uniforms.min_timestep, uniforms.timestep);
+}
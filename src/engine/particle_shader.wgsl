@@ -1,7 +1,8 @@
 struct VertexOutput
 {
     @builtin(position) clip_position : vec4<f32>,
-    @location(0) uv : vec2<f32>
+    @location(0) uv : vec2<f32>,
+    @location(1) colour : vec4<f32>,
 }
 
 struct VertexInput
@@ -17,9 +18,49 @@ struct CameraUniform {
 var<uniform> camera: CameraUniform;
 
 struct InstanceInput {
-    @location(5) offset: vec2<f32>,
+    @location(6) position: vec2<f32>,
+    @location(7) colour: vec4<f32>,
+    @location(9) radius: f32,
+    @location(10) species: u32,
+    @location(12) scalar: f32,
+    @location(13) age: f32,
+    @location(14) lifetime: f32,
 };
 
+struct AtlasUniform {
+    columns: u32,
+    rows: u32,
+    frames_per_species: u32,
+};
+@group(0) @binding(2)
+var<uniform> atlas: AtlasUniform;
+
+// Picks the species' frame for how far the particle is through its lifetime
+fn atlas_uv(uv : vec2<f32>, species : u32, age : f32, lifetime : f32) -> vec2<f32>
+{
+    var step = 0u;
+    if lifetime > 0.
+    {
+        step = min(u32(age / lifetime * f32(atlas.frames_per_species)), atlas.frames_per_species - 1u);
+    }
+    let frame = (species * atlas.frames_per_species + step) % (atlas.columns * atlas.rows);
+    let cell = vec2<f32>(f32(frame % atlas.columns), f32(frame / atlas.columns));
+    return (cell + uv) / vec2<f32>(f32(atlas.columns), f32(atlas.rows));
+}
+
+struct ColourUniform {
+    mode: u32,
+};
+@group(2) @binding(0)
+var<uniform> colouring: ColourUniform;
+@group(2) @binding(1)
+var colour_map: texture_1d<f32>;
+@group(2) @binding(2)
+var colour_map_sampler: sampler;
+
+// Inradius of the triangle in `TRIANGLE_VERTS`
+const TRIANGLE_RADIUS : f32 = 0.5;
+
 
 @vertex
 fn vs_main(
@@ -31,11 +72,39 @@ fn vs_main(
     
     let offset = vec2<f32>(0.86603, 0.5);
 
-    out.clip_position = camera.proj_view * vec4<f32>(model.position.xy + instance.offset - offset, model.position.z, 1.0);
-    out.uv = model.uv;
+    let scale = instance.radius / TRIANGLE_RADIUS;
+
+    out.clip_position = camera.proj_view * vec4<f32>((model.position.xy - offset) * scale + instance.position, model.position.z, 1.0);
+    out.uv = atlas_uv(model.uv, instance.species, instance.age, instance.lifetime);
+    out.colour = instance.colour;
+    if colouring.mode != 0u
+    {
+        out.colour = textureSampleLevel(colour_map, colour_map_sampler, instance.scalar, 0.);
+    }
     return out;
 }
 
+// How far the quad reaches past the radius, leaves room for anti-aliasing and the gaussian tail
+const QUAD_EXTENT : f32 = 1.5;
+
+@vertex
+fn vs_quad(
+    model : VertexInput,
+    instance : InstanceInput
+) -> VertexOutput
+{
+    var out : VertexOutput;
+
+    out.clip_position = camera.proj_view * vec4<f32>(model.position.xy * instance.radius * QUAD_EXTENT + instance.position, model.position.z, 1.0);
+    // In units of the radius, the particle's edge is at a distance of 1
+    out.uv = model.uv * QUAD_EXTENT;
+    out.colour = instance.colour;
+    if colouring.mode != 0u
+    {
+        out.colour = textureSampleLevel(colour_map, colour_map_sampler, instance.scalar, 0.);
+    }
+    return out;
+}
 
 @group(0) @binding(0)
 var texture : texture_2d<f32>;
@@ -45,5 +114,32 @@ var texture_sampler : sampler;
 @fragment 
 fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
     //return vec4<f32>(in.uv.xy, 0., 1.);
-    return textureSample(texture, texture_sampler, in.uv); 
-}
\ No newline at end of file
+    return textureSample(texture, texture_sampler, in.uv) * in.colour;
+}
+
+// Coverage of a shape whose edge is where `distance` crosses zero, smoothed over a pixel
+fn coverage(distance : f32) -> f32
+{
+    let width = max(fwidth(distance), 1e-4);
+    return clamp(0.5 - distance / width, 0., 1.);
+}
+
+@fragment
+fn fs_disc(in : VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = coverage(length(in.uv) - 1.);
+    return vec4<f32>(in.colour.rgb, in.colour.a * alpha);
+}
+
+@fragment
+fn fs_ring(in : VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = coverage(abs(length(in.uv) - 0.9) - 0.1);
+    return vec4<f32>(in.colour.rgb, in.colour.a * alpha);
+}
+
+@fragment
+fn fs_gaussian(in : VertexOutput) -> @location(0) vec4<f32> {
+    // Standard deviation of half the radius
+    let d = length(in.uv);
+    let alpha = exp(-2. * d * d);
+    return vec4<f32>(in.colour.rgb, in.colour.a * alpha);
+}
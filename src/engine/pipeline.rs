@@ -0,0 +1,307 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroupLayout, BlendState, Buffer, BufferSlice, Device, FragmentState,
+    PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor, RenderPass,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor,
+    TextureFormat,
+};
+
+use super::{colour::ParticleColouring, shaders::catch_errors, RawParticleInstance, Vertex};
+
+const TRIANGLE_VERTS: &[Vertex] = &[
+    Vertex {
+        position: [0.86603, 1.5, 0.0],
+        tex_coords: [0.5, 1. - 0.866025],
+    },
+    Vertex {
+        position: [0.0, 0.0, 0.0],
+        tex_coords: [0.0, 1.],
+    },
+    Vertex {
+        position: [1.73205, 0.0, 0.0],
+        tex_coords: [1.0, 1.],
+    },
+];
+
+/// A unit quad, the shader scales it by the particle radius.
+const QUAD_VERTS: &[Vertex] = &[
+    Vertex {
+        position: [-1.0, -1.0, 0.0],
+        tex_coords: [-1.0, -1.0],
+    },
+    Vertex {
+        position: [1.0, -1.0, 0.0],
+        tex_coords: [1.0, -1.0],
+    },
+    Vertex {
+        position: [1.0, 1.0, 0.0],
+        tex_coords: [1.0, 1.0],
+    },
+    Vertex {
+        position: [-1.0, -1.0, 0.0],
+        tex_coords: [-1.0, -1.0],
+    },
+    Vertex {
+        position: [1.0, 1.0, 0.0],
+        tex_coords: [1.0, 1.0],
+    },
+    Vertex {
+        position: [-1.0, 1.0, 0.0],
+        tex_coords: [-1.0, 1.0],
+    },
+];
+
+/// Sums overlapping particles, used when rendering into an HDR target.
+pub const ADDITIVE_BLENDING: BlendState = BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+/// How a single particle is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// A triangle textured with the sprite atlas.
+    Sprite,
+    /// An anti-aliased disc exactly the size of the particle radius.
+    Disc,
+    /// The outline of the disc.
+    Ring,
+    /// A soft gaussian blob with a standard deviation of half the radius.
+    Gaussian,
+    /// Particle density splatted into a grid, drawn by the heatmap rather than this pipeline.
+    Heatmap,
+}
+
+impl RenderMode {
+    const ALL: [RenderMode; 5] = [
+        RenderMode::Sprite,
+        RenderMode::Disc,
+        RenderMode::Ring,
+        RenderMode::Gaussian,
+        RenderMode::Heatmap,
+    ];
+
+    pub fn next(self) -> Self {
+        match self {
+            RenderMode::Sprite => RenderMode::Disc,
+            RenderMode::Disc => RenderMode::Ring,
+            RenderMode::Ring => RenderMode::Gaussian,
+            RenderMode::Gaussian => RenderMode::Heatmap,
+            RenderMode::Heatmap => RenderMode::Sprite,
+        }
+    }
+
+    fn entry_points(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            RenderMode::Sprite => Some(("vs_main", "fs_main")),
+            RenderMode::Disc => Some(("vs_quad", "fs_disc")),
+            RenderMode::Ring => Some(("vs_quad", "fs_ring")),
+            RenderMode::Gaussian => Some(("vs_quad", "fs_gaussian")),
+            RenderMode::Heatmap => None,
+        }
+    }
+}
+
+/// The render pipeline drawing particles, rebuilt whenever the mode or target changes.
+pub struct ParticlePipeline {
+    shader: ShaderModule,
+    layout: PipelineLayout,
+    triangle_buffer: Buffer,
+    quad_buffer: Buffer,
+
+    mode: RenderMode,
+    format: TextureFormat,
+    blend: BlendState,
+    pipeline: RenderPipeline,
+}
+
+impl ParticlePipeline {
+    pub fn new(
+        device: &Device,
+        bind_group_layouts: &[&BindGroupLayout],
+        format: TextureFormat,
+    ) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("particle_shader.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Particle Shader Layout"),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let triangle_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Triangle Vertex Buffer"),
+            contents: bytemuck::cast_slice(TRIANGLE_VERTS),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let quad_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Quad Vertex Buffer"),
+            contents: bytemuck::cast_slice(QUAD_VERTS),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let mode = RenderMode::Sprite;
+        let blend = BlendState::ALPHA_BLENDING;
+        let pipeline = Self::create_pipeline(
+            device,
+            &layout,
+            &shader,
+            ("vs_main", "fs_main"),
+            format,
+            blend,
+        );
+
+        Self {
+            shader,
+            layout,
+            triangle_buffer,
+            quad_buffer,
+            mode,
+            format,
+            blend,
+            pipeline,
+        }
+    }
+
+    pub fn mode(&self) -> RenderMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: RenderMode, device: &Device) {
+        self.mode = mode;
+        self.rebuild(device);
+    }
+
+    /// Changes the format and blending of the texture particles are drawn into.
+    pub fn set_target(&mut self, format: TextureFormat, blend: BlendState, device: &Device) {
+        self.format = format;
+        self.blend = blend;
+        self.rebuild(device);
+    }
+
+    /// Swaps in a new version of `particle_shader.wgsl`. Every render mode is built
+    /// with it first, on error the current shader stays in use.
+    pub fn set_shader(&mut self, source: &str, device: &Device) -> Result<(), String> {
+        let shader = catch_errors(device, || {
+            let shader = device.create_shader_module(ShaderModuleDescriptor {
+                label: Some("Particle Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            for entry_points in RenderMode::ALL.iter().filter_map(|mode| mode.entry_points()) {
+                Self::create_pipeline(
+                    device,
+                    &self.layout,
+                    &shader,
+                    entry_points,
+                    self.format,
+                    self.blend,
+                );
+            }
+            shader
+        })?;
+
+        self.shader = shader;
+        self.rebuild(device);
+        Ok(())
+    }
+
+    fn rebuild(&mut self, device: &Device) {
+        // Keeps the last particle pipeline around while the heatmap is shown
+        let Some(entry_points) = self.mode.entry_points() else {
+            return;
+        };
+
+        self.pipeline = Self::create_pipeline(
+            device,
+            &self.layout,
+            &self.shader,
+            entry_points,
+            self.format,
+            self.blend,
+        );
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        layout: &PipelineLayout,
+        shader: &ShaderModule,
+        (vertex_entry, fragment_entry): (&str, &str),
+        format: TextureFormat,
+        blend: BlendState,
+    ) -> RenderPipeline {
+
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Particle Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vertex_entry,
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[
+                    Vertex::desc(),
+                    RawParticleInstance::desc(),
+                    ParticleColouring::desc(),
+                ],
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: fragment_entry,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    /// Draws `count` particles, the bind groups are expected to be set already.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        particles: BufferSlice<'a>,
+        scalars: BufferSlice<'a>,
+        count: u32,
+    ) {
+        let (vertices, vertex_count) = match self.mode {
+            RenderMode::Sprite => (&self.triangle_buffer, TRIANGLE_VERTS.len()),
+            _ => (&self.quad_buffer, QUAD_VERTS.len()),
+        };
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, vertices.slice(..));
+        render_pass.set_vertex_buffer(1, particles);
+        render_pass.set_vertex_buffer(2, scalars);
+        render_pass.draw(0..vertex_count as u32, 0..count);
+    }
+}
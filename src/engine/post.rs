@@ -0,0 +1,393 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Color, CommandEncoder, Device, FilterMode, FragmentState,
+    Operations, PipelineCompilationOptions, PipelineLayoutDescriptor, Queue, RenderPass,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, StoreOp, TextureDescriptor,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+    TextureViewDimension,
+};
+use winit::dpi::PhysicalSize;
+
+/// Format of the offscreen target particles are accumulated into.
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemap {
+    Aces,
+    Reinhard,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HdrSettings {
+    pub tonemap: Tonemap,
+    /// Multiplier applied to the accumulated light before tonemapping.
+    pub exposure: f32,
+    pub bloom: bool,
+    /// Brightness above which pixels contribute to bloom.
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+}
+
+impl Default for HdrSettings {
+    fn default() -> Self {
+        Self {
+            tonemap: Tonemap::Aces,
+            exposure: 0.5,
+            bloom: true,
+            bloom_threshold: 1.,
+            bloom_intensity: 0.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct PostUniform {
+    exposure: f32,
+    tonemap: u32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+}
+
+impl PostUniform {
+    fn new(settings: &HdrSettings) -> Self {
+        Self {
+            exposure: settings.exposure,
+            tonemap: match settings.tonemap {
+                Tonemap::Aces => 0,
+                Tonemap::Reinhard => 1,
+            },
+            bloom_threshold: settings.bloom_threshold,
+            bloom_intensity: if settings.bloom {
+                settings.bloom_intensity
+            } else {
+                0.
+            },
+        }
+    }
+}
+
+struct Targets {
+    hdr: TextureView,
+    /// Half resolution ping-pong targets for the bloom blur.
+    bloom: [TextureView; 2],
+
+    bright_group: BindGroup,
+    blur_groups: [BindGroup; 2],
+    tonemap_group: BindGroup,
+}
+
+/// Renders particles additively into an HDR target, then blooms and tonemaps it onto the output.
+pub struct PostProcess {
+    settings: HdrSettings,
+    uniform_buffer: Buffer,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+
+    bright_pipeline: RenderPipeline,
+    blur_pipelines: [RenderPipeline; 2],
+    tonemap_pipeline: RenderPipeline,
+
+    targets: Targets,
+}
+
+impl PostProcess {
+    pub fn new(
+        settings: HdrSettings,
+        size: PhysicalSize<u32>,
+        output_format: TextureFormat,
+        device: &Device,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Post Process Uniform"),
+            contents: bytemuck::cast_slice(&[PostUniform::new(&settings)]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Post Process Bind Group Layout"),
+            entries: &[
+                texture(0),
+                texture(1),
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("post.wgsl"));
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Post Process Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point, format| {
+            fullscreen_pipeline(device, &layout, &shader, entry_point, format, None)
+        };
+
+        let bright_pipeline = pipeline("fs_bright", HDR_FORMAT);
+        let blur_pipelines = [
+            pipeline("fs_blur_horizontal", HDR_FORMAT),
+            pipeline("fs_blur_vertical", HDR_FORMAT),
+        ];
+        let tonemap_pipeline = pipeline("fs_tonemap", output_format);
+
+        let targets = Self::create_targets(
+            size,
+            &bind_group_layout,
+            &sampler,
+            &uniform_buffer,
+            device,
+        );
+
+        Self {
+            settings,
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            bright_pipeline,
+            blur_pipelines,
+            tonemap_pipeline,
+            targets,
+        }
+    }
+
+    fn create_targets(
+        size: PhysicalSize<u32>,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        uniform_buffer: &Buffer,
+        device: &Device,
+    ) -> Targets {
+        let target = |label, width: u32, height: u32| {
+            device
+                .create_texture(&TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: width.max(1),
+                        height: height.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: HDR_FORMAT,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&TextureViewDescriptor::default())
+        };
+
+        let hdr = target("HDR Target", size.width, size.height);
+        let bloom = [
+            target("Bloom Target A", size.width / 2, size.height / 2),
+            target("Bloom Target B", size.width / 2, size.height / 2),
+        ];
+
+        let group = |source: &TextureView, bloom: &TextureView| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Post Process Bind Group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(source),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(bloom),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+
+        // The second texture is only read by the tonemap pass, the others bind
+        // anything that isn't their render target
+        let bright_group = group(&hdr, &bloom[1]);
+        let blur_groups = [group(&bloom[0], &hdr), group(&bloom[1], &hdr)];
+        let tonemap_group = group(&hdr, &bloom[0]);
+
+        Targets {
+            hdr,
+            bloom,
+            bright_group,
+            blur_groups,
+            tonemap_group,
+        }
+    }
+
+    pub fn settings(&self) -> HdrSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: HdrSettings, queue: &Queue) {
+        self.settings = settings;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[PostUniform::new(&settings)]),
+        );
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>, device: &Device) {
+        self.targets = Self::create_targets(
+            size,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.uniform_buffer,
+            device,
+        );
+    }
+
+    /// The target particles should be drawn into.
+    pub fn hdr_view(&self) -> &TextureView {
+        &self.targets.hdr
+    }
+
+    /// Blooms and tonemaps the HDR target into `output`. The tonemap pass is returned still
+    /// open, for drawing overlays in.
+    pub fn apply<'e>(
+        &self,
+        encoder: &'e mut CommandEncoder,
+        output: &TextureView,
+    ) -> RenderPass<'e> {
+        if self.settings.bloom {
+            fullscreen_pass(
+                encoder,
+                "Bloom Bright Pass",
+                &self.targets.bloom[0],
+                &self.bright_pipeline,
+                &self.targets.bright_group,
+            );
+            fullscreen_pass(
+                encoder,
+                "Bloom Horizontal Blur",
+                &self.targets.bloom[1],
+                &self.blur_pipelines[0],
+                &self.targets.blur_groups[0],
+            );
+            fullscreen_pass(
+                encoder,
+                "Bloom Vertical Blur",
+                &self.targets.bloom[0],
+                &self.blur_pipelines[1],
+                &self.targets.blur_groups[1],
+            );
+        }
+
+        fullscreen_pass(
+            encoder,
+            "Tonemap Pass",
+            output,
+            &self.tonemap_pipeline,
+            &self.targets.tonemap_group,
+        )
+    }
+}
+
+/// A pipeline drawing the full screen triangle from `vs_fullscreen`.
+pub(crate) fn fullscreen_pipeline(
+    device: &Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fragment_entry: &str,
+    format: TextureFormat,
+    blend: Option<wgpu::BlendState>,
+) -> RenderPipeline {
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(fragment_entry),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_fullscreen",
+            compilation_options: PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module: shader,
+            entry_point: fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn fullscreen_pass<'e>(
+    encoder: &'e mut CommandEncoder,
+    label: &str,
+    target: &TextureView,
+    pipeline: &RenderPipeline,
+    bind_group: &BindGroup,
+) -> RenderPass<'e> {
+    let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: Operations {
+                load: wgpu::LoadOp::Clear(Color::BLACK),
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+    pass
+}
@@ -0,0 +1,96 @@
+struct VertexOutput
+{
+    @builtin(position) clip_position : vec4<f32>,
+    @location(0) uv : vec2<f32>,
+}
+
+struct PostUniform
+{
+    exposure : f32,
+    tonemap : u32,
+    bloom_threshold : f32,
+    bloom_intensity : f32,
+}
+
+@group(0) @binding(0)
+var source : texture_2d<f32>;
+@group(0) @binding(1)
+var bloom : texture_2d<f32>;
+@group(0) @binding(2)
+var source_sampler : sampler;
+@group(0) @binding(3)
+var<uniform> post : PostUniform;
+
+const REINHARD : u32 = 1u;
+
+// A single triangle covering the screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index : u32) -> VertexOutput
+{
+    var out : VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * 2. - 1., 0., 1.);
+    out.uv = vec2<f32>(uv.x, 1. - uv.y);
+    return out;
+}
+
+@fragment
+fn fs_bright(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    let colour = textureSample(source, source_sampler, in.uv).rgb * post.exposure;
+    let brightness = max(colour.r, max(colour.g, colour.b));
+    let contribution = max(brightness - post.bloom_threshold, 0.) / max(brightness, 1e-4);
+    return vec4<f32>(colour * contribution, 1.);
+}
+
+// 9 tap gaussian using linear sampling, https://www.rastergrid.com/blog/2010/09/efficient-gaussian-blur-with-linear-sampling/
+fn blur(uv : vec2<f32>, direction : vec2<f32>) -> vec4<f32>
+{
+    let texel = direction / vec2<f32>(textureDimensions(source));
+    var offsets = array<f32, 3>(0., 1.3846153846, 3.2307692308);
+    var weights = array<f32, 3>(0.2270270270, 0.3162162162, 0.0702702703);
+
+    var colour = textureSample(source, source_sampler, uv).rgb * weights[0];
+    for (var i = 1; i < 3; i++)
+    {
+        colour += textureSample(source, source_sampler, uv + texel * offsets[i]).rgb * weights[i];
+        colour += textureSample(source, source_sampler, uv - texel * offsets[i]).rgb * weights[i];
+    }
+    return vec4<f32>(colour, 1.);
+}
+
+@fragment
+fn fs_blur_horizontal(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    return blur(in.uv, vec2<f32>(1., 0.));
+}
+
+@fragment
+fn fs_blur_vertical(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    return blur(in.uv, vec2<f32>(0., 1.));
+}
+
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+fn aces(x : vec3<f32>) -> vec3<f32>
+{
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.), vec3<f32>(1.));
+}
+
+@fragment
+fn fs_tonemap(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    let hdr = textureSample(source, source_sampler, in.uv).rgb * post.exposure
+        + textureSample(bloom, source_sampler, in.uv).rgb * post.bloom_intensity;
+
+    if post.tonemap == REINHARD
+    {
+        return vec4<f32>(hdr / (hdr + 1.), 1.);
+    }
+    return vec4<f32>(aces(hdr), 1.);
+}
@@ -0,0 +1,203 @@
+use std::cell::Cell;
+
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassTimestampWrites, Device,
+    Features, Maintain, QuerySet, QuerySetDescriptor, QueryType, Queue, RenderPassTimestampWrites,
+    QUERY_SIZE,
+};
+
+use super::capture::MapResult;
+
+/// A pass timed on the GPU, see [`GpuProfiler`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpuPass {
+    /// The particle physics compute pass.
+    Simulate,
+    /// Computing the scalars particles are coloured by.
+    Colour,
+    /// Splatting particles into the heatmap.
+    Heatmap,
+    /// Drawing the particles or heatmap.
+    Draw,
+}
+
+const PASSES: [GpuPass; 4] = [GpuPass::Simulate, GpuPass::Colour, GpuPass::Heatmap, GpuPass::Draw];
+/// A start and end timestamp for each pass.
+const QUERY_COUNT: u32 = PASSES.len() as u32 * 2;
+
+/// GPU time spent in each pass of one frame, in seconds. Passes that did not run are `0`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GpuTimes {
+    pub simulate: f32,
+    pub colour: f32,
+    pub heatmap: f32,
+    pub draw: f32,
+}
+
+impl GpuTimes {
+    pub fn total(&self) -> f32 {
+        self.simulate + self.colour + self.heatmap + self.draw
+    }
+}
+
+/// Times passes with timestamp queries, only available with [`Features::TIMESTAMP_QUERY`].
+///
+/// The results are read back without stalling, so they arrive a frame or two after the
+/// frame they were measured in. Frames rendered while the previous readback is still in
+/// flight are not timed.
+pub struct GpuProfiler {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+
+    /// The passes that wrote their timestamps this frame.
+    written: Cell<u32>,
+    /// The passes of the frame being read back.
+    resolved: u32,
+    copied: bool,
+    in_flight: bool,
+    mapped: MapResult,
+}
+
+impl GpuProfiler {
+    /// Returns `None` when the device was created without timestamp queries.
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("GPU Profiler Queries"),
+            ty: QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+
+        let size = QUERY_COUNT as u64 * QUERY_SIZE as u64;
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("GPU Profiler Resolve Buffer"),
+            size,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("GPU Profiler Readback Buffer"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            written: Cell::new(0),
+            resolved: 0,
+            copied: false,
+            in_flight: false,
+            mapped: MapResult::default(),
+        })
+    }
+
+    fn index(pass: GpuPass) -> u32 {
+        PASSES.iter().position(|p| *p == pass).unwrap() as u32
+    }
+
+    fn mark(&self, pass: GpuPass) -> u32 {
+        let index = Self::index(pass);
+        self.written.set(self.written.get() | 1 << index);
+        index * 2
+    }
+
+    /// Timestamps for a compute pass, only ask for them when the pass is actually recorded.
+    pub fn compute_writes(&self, pass: GpuPass) -> ComputePassTimestampWrites<'_> {
+        let start = self.mark(pass);
+        ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(start + 1),
+        }
+    }
+
+    /// Timestamps for a render pass, only ask for them when the pass is actually recorded.
+    pub fn render_writes(&self, pass: GpuPass) -> RenderPassTimestampWrites<'_> {
+        let start = self.mark(pass);
+        RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(start + 1),
+        }
+    }
+
+    /// Copies this frame's timestamps out for reading, call once all passes are recorded.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder) {
+        let written = self.written.replace(0);
+        if self.in_flight || written == 0 {
+            return;
+        }
+
+        encoder.resolve_query_set(&self.query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            self.resolve_buffer.size(),
+        );
+        self.resolved = written;
+        self.copied = true;
+    }
+
+    /// Starts reading back the timestamps resolved this frame, call after submitting.
+    pub fn map(&mut self) {
+        if !std::mem::replace(&mut self.copied, false) {
+            return;
+        }
+
+        self.mapped.map(&self.readback_buffer);
+        self.in_flight = true;
+    }
+
+    /// The times of the last frame read back, if they have arrived since the last call.
+    pub fn poll(&mut self, device: &Device) -> Option<GpuTimes> {
+        if !self.in_flight {
+            return None;
+        }
+
+        device.poll(Maintain::Poll);
+        let mapped = self
+            .mapped
+            .take(&self.readback_buffer, device, "GPU timings")?;
+        if !mapped {
+            // Skip the frame, the next one is read back as usual
+            self.in_flight = false;
+            return None;
+        }
+
+        let timestamps: Vec<u64> = {
+            let view = self.readback_buffer.slice(..).get_mapped_range();
+            bytemuck::cast_slice(&view).to_vec()
+        };
+        self.readback_buffer.unmap();
+        self.in_flight = false;
+
+        let duration = |pass: GpuPass| {
+            let index = Self::index(pass);
+            if self.resolved & 1 << index == 0 {
+                return 0.;
+            }
+            let start = timestamps[index as usize * 2];
+            let end = timestamps[index as usize * 2 + 1];
+            end.saturating_sub(start) as f32 * self.period * 1e-9
+        };
+
+        Some(GpuTimes {
+            simulate: duration(GpuPass::Simulate),
+            colour: duration(GpuPass::Colour),
+            heatmap: duration(GpuPass::Heatmap),
+            draw: duration(GpuPass::Draw),
+        })
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, ImageResult, RgbaImage,
+};
+use winit::dpi::PhysicalSize;
+
+enum Output {
+    /// Numbered PNG files in a directory.
+    Png(PathBuf),
+    Gif(GifEncoder<BufWriter<File>>),
+}
+
+/// Captures every `every`th simulation step offscreen at a fixed resolution, starting
+/// with the first.
+///
+/// Frames are taken by simulation step rather than wall clock time, so the output
+/// plays back at simulation speed however fast the frames were rendered.
+pub struct Recorder {
+    output: Output,
+    size: PhysicalSize<u32>,
+    every: u32,
+    frames: u32,
+}
+
+impl Recorder {
+    /// Writes `frame_00000.png`, `frame_00001.png`, ... into `directory`.
+    pub fn png_sequence(
+        directory: impl AsRef<Path>,
+        size: PhysicalSize<u32>,
+        every: u32,
+    ) -> ImageResult<Self> {
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            output: Output::Png(directory.as_ref().to_path_buf()),
+            size,
+            every: every.max(1),
+            frames: 0,
+        })
+    }
+
+    /// Writes a looping animated GIF to `path`.
+    pub fn gif(path: impl AsRef<Path>, size: PhysicalSize<u32>, every: u32) -> ImageResult<Self> {
+        let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+        encoder.set_repeat(Repeat::Infinite)?;
+        Ok(Self {
+            output: Output::Gif(encoder),
+            size,
+            every: every.max(1),
+            frames: 0,
+        })
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Number of frames written so far.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Whether the frame after simulation step `step` should be captured, steps count from 1.
+    pub fn wants(&self, step: u64) -> bool {
+        step.saturating_sub(1) % self.every as u64 == 0
+    }
+
+    /// Writes `frame`, a GIF shows it for `every` steps of `timestep` seconds of simulated time.
+    pub fn record(&mut self, frame: RgbaImage, timestep: f32) -> ImageResult<()> {
+        match &mut self.output {
+            Output::Png(directory) => {
+                frame.save_with_format(
+                    directory.join(format!("frame_{:05}.png", self.frames)),
+                    image::ImageFormat::Png,
+                )?;
+            }
+            Output::Gif(encoder) => {
+                let delay = Delay::from_saturating_duration(Duration::from_secs_f32(
+                    timestep * self.every as f32,
+                ));
+                encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Finishes the file, the GIF trailer is written when the encoder is dropped.
+    pub fn finish(self) {
+        log::info!("Recorded {} frames", self.frames);
+    }
+}
@@ -0,0 +1,851 @@
+use std::{f32::consts::TAU, fmt, path::Path};
+
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+use crate::SIDE_LENGTH;
+
+use super::{
+    compute::TIMESTEP,
+    generators::{self, Orbits, Rng, Sample},
+    ParticleAttributes, ParticleInstance,
+};
+
+/// Initial conditions and simulation parameters, usually loaded from a TOML file.
+///
+/// ```toml
+/// [simulation]
+/// timestep = 0.016666
+///
+/// [[group]]
+/// shape = "disc"
+/// center = [1250.0, 1250.0]
+/// radius = 400.0
+/// count = 100000
+/// velocity = [0.0, 30.0]
+/// attributes = { mass = 2.0, colour = [1.0, 0.5, 0.2, 1.0] }
+///
+/// [[attractor]]
+/// position = [1250.0, 1250.0]
+/// strength = 54000.0
+///
+/// [[collider]]
+/// shape = "circle"
+/// center = [1250.0, 600.0]
+/// radius = 100.0
+///
+/// [boundary]
+/// mode = "wrap"
+///
+/// [camera]
+/// zoom = 1.5
+/// ```
+///
+/// Every table is optional, a missing one keeps its default. Without any groups the
+/// scene is the dense grid covering the world.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    #[serde(default)]
+    pub simulation: SimulationConfig,
+    #[serde(default, rename = "group")]
+    pub groups: Vec<ParticleGroup>,
+    #[serde(default = "default_attractors", rename = "attractor")]
+    pub attractors: Vec<Attractor>,
+    #[serde(default, rename = "collider")]
+    pub colliders: Vec<Collider>,
+    #[serde(default)]
+    pub boundary: Boundary,
+    #[serde(default)]
+    pub camera: CameraConfig,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            simulation: SimulationConfig::default(),
+            groups: Vec::new(),
+            attractors: default_attractors(),
+            colliders: Vec::new(),
+            boundary: Boundary::default(),
+            camera: CameraConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    /// A value the simulation can't run with, like a zoom of zero.
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "failed to read scene: {}", e),
+            SceneError::Parse(e) => write!(f, "failed to parse scene: {}", e),
+            SceneError::Invalid(e) => write!(f, "invalid scene: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(e: toml::de::Error) -> Self {
+        SceneError::Parse(e)
+    }
+}
+
+/// How positions are advanced each step, each has its own entry point in
+/// `particle_compute.wgsl`.
+///
+/// Velocities are always stored as the displacement over the last step, so the
+/// integrators can be switched between without changing the particle layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// Position Verlet, velocity is implied by the previous position.
+    #[default]
+    Verlet,
+    /// Velocity Verlet as kick-drift-kick, two force evaluations per step.
+    Leapfrog,
+    /// Fourth order Runge-Kutta, four force evaluations per step and not symplectic.
+    Rk4,
+}
+
+impl Integrator {
+    pub fn next(self) -> Self {
+        match self {
+            Integrator::Verlet => Integrator::Leapfrog,
+            Integrator::Leapfrog => Integrator::Rk4,
+            Integrator::Rk4 => Integrator::Verlet,
+        }
+    }
+
+    pub(crate) fn entry_point(self) -> &'static str {
+        match self {
+            Integrator::Verlet => "main_verlet",
+            Integrator::Leapfrog => "main_leapfrog",
+            Integrator::Rk4 => "main_rk4",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    pub integrator: Integrator,
+    /// Seconds simulated per step, the largest step when adaptive.
+    pub timestep: f32,
+    /// Shortens steps while particles move fast or pass close to an attractor.
+    pub adaptive: Option<AdaptiveTimestep>,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            integrator: Integrator::Verlet,
+            timestep: TIMESTEP,
+            adaptive: None,
+        }
+    }
+}
+
+/// Limits each step's length by the fastest and the most accelerated particle,
+/// given as `[simulation.adaptive]`.
+///
+/// The next timestep is picked on the GPU from the maxima of the last step, and grows
+/// by at most a factor of two per step.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveTimestep {
+    /// World units the fastest particle may move in one step.
+    pub max_displacement: f32,
+    /// World units the most accelerated particle may stray from a straight line in one step.
+    pub accuracy: f32,
+    /// Shortest step in seconds, the longest is [`SimulationConfig::timestep`].
+    pub min: f32,
+}
+
+impl Default for AdaptiveTimestep {
+    fn default() -> Self {
+        Self {
+            max_displacement: 2.,
+            accuracy: 0.05,
+            min: TIMESTEP / 1000.,
+        }
+    }
+}
+
+/// Where the particles of a group start.
+///
+/// The first few spread `count` particles evenly over the shape, the rest are random
+/// draws from [`generators`](super::generators) that are reproducible for a given `seed`.
+/// The rotating ones put particles on circular orbits around an attractor of `strength`
+/// and `falloff` at their centre, which defaults to the default [`Attractor`].
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Shape {
+    /// Rows and columns filling the rectangle between `min` and `max`.
+    Grid { min: [f32; 2], max: [f32; 2] },
+    /// A filled disc, laid out along a sunflower spiral.
+    Disc { center: [f32; 2], radius: f32 },
+    /// The outline of a circle.
+    Ring { center: [f32; 2], radius: f32 },
+    /// A line segment.
+    Line { start: [f32; 2], end: [f32; 2] },
+    UniformBox {
+        min: [f32; 2],
+        max: [f32; 2],
+        #[serde(default)]
+        seed: u64,
+    },
+    UniformDisc {
+        center: [f32; 2],
+        radius: f32,
+        #[serde(default)]
+        seed: u64,
+    },
+    Gaussian {
+        center: [f32; 2],
+        sigma: f32,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// A Plummer sphere seen from above, `strength` is its own gravitational parameter.
+    Plummer {
+        center: [f32; 2],
+        scale_radius: f32,
+        #[serde(default = "default_strength")]
+        strength: f32,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// A rotating exponential disc galaxy, cut off at `radius`.
+    ExponentialDisc {
+        center: [f32; 2],
+        scale_length: f32,
+        radius: f32,
+        #[serde(default = "default_strength")]
+        strength: f32,
+        #[serde(default = "default_falloff")]
+        falloff: f32,
+        #[serde(default)]
+        clockwise: bool,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// A rotating annulus around a central mass.
+    KeplerianRing {
+        center: [f32; 2],
+        inner_radius: f32,
+        outer_radius: f32,
+        #[serde(default = "default_strength")]
+        strength: f32,
+        #[serde(default = "default_falloff")]
+        falloff: f32,
+        #[serde(default)]
+        clockwise: bool,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+impl Shape {
+    fn validate(&self, name: impl Fn(&str) -> String) -> Result<(), SceneError> {
+        match *self {
+            Shape::Disc { radius, .. }
+            | Shape::Ring { radius, .. }
+            | Shape::UniformDisc { radius, .. } => positive(&name("radius"), radius),
+            Shape::Gaussian { sigma, .. } => positive(&name("sigma"), sigma),
+            Shape::Plummer { scale_radius, .. } => positive(&name("scale_radius"), scale_radius),
+            Shape::KeplerianRing {
+                inner_radius,
+                outer_radius,
+                ..
+            } => {
+                if inner_radius < 0. || outer_radius <= inner_radius {
+                    return Err(SceneError::Invalid(format!(
+                        "{} must be positive and below {}, not {} and {}",
+                        name("inner_radius"),
+                        name("outer_radius"),
+                        inner_radius,
+                        outer_radius
+                    )));
+                }
+                Ok(())
+            }
+            Shape::ExponentialDisc {
+                scale_length,
+                radius,
+                ..
+            } => {
+                positive(&name("scale_length"), scale_length)?;
+                positive(&name("radius"), radius)
+            }
+            Shape::Grid { .. } | Shape::Line { .. } | Shape::UniformBox { .. } => Ok(()),
+        }
+    }
+
+    /// The seed of the random shapes.
+    fn seed_mut(&mut self) -> Option<&mut u64> {
+        match self {
+            Shape::UniformBox { seed, .. }
+            | Shape::UniformDisc { seed, .. }
+            | Shape::Gaussian { seed, .. }
+            | Shape::Plummer { seed, .. }
+            | Shape::ExponentialDisc { seed, .. }
+            | Shape::KeplerianRing { seed, .. } => Some(seed),
+            Shape::Grid { .. } | Shape::Disc { .. } | Shape::Ring { .. } | Shape::Line { .. } => {
+                None
+            }
+        }
+    }
+}
+
+fn positive(name: &str, value: f32) -> Result<(), SceneError> {
+    if value > 0. && value.is_finite() {
+        Ok(())
+    } else {
+        Err(SceneError::Invalid(format!(
+            "{} must be positive, not {}",
+            name, value
+        )))
+    }
+}
+
+fn default_strength() -> f32 {
+    Attractor::default().strength
+}
+
+fn default_falloff() -> f32 {
+    Attractor::default().falloff
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ParticleGroup {
+    #[serde(flatten)]
+    pub shape: Shape,
+    pub count: usize,
+    /// Initial velocity in world units per second, added to any the shape gives.
+    #[serde(default)]
+    pub velocity: [f32; 2],
+    #[serde(default)]
+    pub attributes: ParticleAttributes,
+}
+
+/// A point pulling particles towards it with `strength / distance^falloff`, divided by
+/// the particle mass.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Attractor {
+    pub position: [f32; 2],
+    /// World units per second the attractor moves at.
+    pub velocity: [f32; 2],
+    /// Negative strengths repel.
+    pub strength: f32,
+    pub falloff: f32,
+    /// Added to the distance in quadrature, keeps the force finite near the attractor.
+    pub softening: f32,
+}
+
+impl Default for Attractor {
+    /// The attractor the simulation always had, at the centre of the world.
+    fn default() -> Self {
+        Self {
+            position: [SIDE_LENGTH as f32 / 2., SIDE_LENGTH as f32 / 2.],
+            velocity: [0., 0.],
+            strength: 54000.,
+            falloff: 1.,
+            softening: 0.,
+        }
+    }
+}
+
+fn default_attractors() -> Vec<Attractor> {
+    vec![Attractor::default()]
+}
+
+/// A solid obstacle particles bounce off.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Collider {
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+        #[serde(default = "default_restitution")]
+        restitution: f32,
+    },
+    /// An axis aligned box.
+    Rect {
+        min: [f32; 2],
+        max: [f32; 2],
+        #[serde(default = "default_restitution")]
+        restitution: f32,
+    },
+}
+
+fn default_restitution() -> f32 {
+    1.
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// Particles leave the world freely.
+    #[default]
+    None,
+    /// Particles bounce off the edges.
+    Reflect,
+    /// Particles leaving one edge come back in on the opposite one.
+    Wrap,
+}
+
+/// The edges of the world, open unless a mode is picked.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Boundary {
+    pub mode: BoundaryMode,
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    /// Fraction of the speed kept when reflecting.
+    pub restitution: f32,
+}
+
+impl Default for Boundary {
+    fn default() -> Self {
+        Self {
+            mode: BoundaryMode::None,
+            min: [0., 0.],
+            max: [SIDE_LENGTH as f32, SIDE_LENGTH as f32],
+            restitution: 1.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// World position of the bottom left corner of the screen.
+    pub position: [f32; 2],
+    pub zoom: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            position: [0., 0.],
+            zoom: 1.,
+        }
+    }
+}
+
+/// GPU layout of an attractor, must match `Attractor` in `particle_compute.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub(crate) struct RawAttractor {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    strength: f32,
+    falloff: f32,
+    softening: f32,
+    _padding: f32,
+}
+
+/// GPU layout of a collider, must match `Collider` in `particle_compute.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+pub(crate) struct RawCollider {
+    kind: u32,
+    restitution: f32,
+    /// The centre of a circle or the minimum of a box.
+    a: [f32; 2],
+    /// The radius of a circle in `x` or the maximum of a box.
+    b: [f32; 2],
+}
+
+impl Attractor {
+    pub(crate) fn raw(&self) -> RawAttractor {
+        RawAttractor {
+            position: self.position,
+            velocity: self.velocity,
+            strength: self.strength,
+            falloff: self.falloff,
+            softening: self.softening,
+            _padding: 0.,
+        }
+    }
+}
+
+impl Collider {
+    pub(crate) fn raw(&self) -> RawCollider {
+        match *self {
+            Collider::Circle {
+                center,
+                radius,
+                restitution,
+            } => RawCollider {
+                kind: 0,
+                restitution,
+                a: center,
+                b: [radius, 0.],
+            },
+            Collider::Rect {
+                min,
+                max,
+                restitution,
+            } => RawCollider {
+                kind: 1,
+                restitution,
+                a: min,
+                b: max,
+            },
+        }
+    }
+}
+
+impl BoundaryMode {
+    pub(crate) fn id(self) -> u32 {
+        match self {
+            BoundaryMode::None => 0,
+            BoundaryMode::Reflect => 1,
+            BoundaryMode::Wrap => 2,
+        }
+    }
+}
+
+impl ParticleGroup {
+    /// The positions and velocities of the group's particles, including the group velocity.
+    pub fn samples(&self) -> Vec<Sample> {
+        let mut samples = self.shape_samples();
+        for sample in &mut samples {
+            sample.velocity[0] += self.velocity[0];
+            sample.velocity[1] += self.velocity[1];
+        }
+        samples
+    }
+
+    fn shape_samples(&self) -> Vec<Sample> {
+        let count = self.count;
+        let mass = self.attributes.mass;
+        match self.shape {
+            Shape::UniformBox { min, max, seed } => {
+                generators::uniform_box(&mut Rng::new(seed), count, min, max)
+            }
+            Shape::UniformDisc {
+                center,
+                radius,
+                seed,
+            } => generators::uniform_disc(&mut Rng::new(seed), count, center, radius),
+            Shape::Gaussian {
+                center,
+                sigma,
+                seed,
+            } => generators::gaussian(&mut Rng::new(seed), count, center, sigma),
+            Shape::Plummer {
+                center,
+                scale_radius,
+                strength,
+                seed,
+            } => generators::plummer(&mut Rng::new(seed), count, center, scale_radius, strength),
+            Shape::ExponentialDisc {
+                center,
+                scale_length,
+                radius,
+                strength,
+                falloff,
+                clockwise,
+                seed,
+            } => generators::exponential_disc(
+                &mut Rng::new(seed),
+                count,
+                center,
+                scale_length,
+                radius,
+                Orbits {
+                    strength,
+                    falloff,
+                    mass,
+                    clockwise,
+                },
+            ),
+            Shape::KeplerianRing {
+                center,
+                inner_radius,
+                outer_radius,
+                strength,
+                falloff,
+                clockwise,
+                seed,
+            } => generators::keplerian_ring(
+                &mut Rng::new(seed),
+                count,
+                center,
+                inner_radius,
+                outer_radius,
+                Orbits {
+                    strength,
+                    falloff,
+                    mass,
+                    clockwise,
+                },
+            ),
+            _ => self
+                .positions()
+                .into_iter()
+                .map(|position| Sample {
+                    position,
+                    velocity: [0., 0.],
+                })
+                .collect(),
+        }
+    }
+
+    /// The evenly spread shapes, which start at rest.
+    fn positions(&self) -> Vec<[f32; 2]> {
+        let count = self.count;
+        let t = |i: usize| (i as f32 + 0.5) / count as f32;
+
+        match self.shape {
+            Shape::Grid { min, max } => {
+                let width = (max[0] - min[0]).abs().max(f32::EPSILON);
+                let height = (max[1] - min[1]).abs().max(f32::EPSILON);
+                let rows = ((count as f32 * height / width).sqrt().ceil() as usize).max(1);
+                let columns = count.div_ceil(rows).max(1);
+
+                // Column by column, like the original grid
+                (0..count)
+                    .map(|i| {
+                        [
+                            min[0] + ((i / rows) as f32 + 0.5) * width / columns as f32,
+                            min[1] + ((i % rows) as f32 + 0.5) * height / rows as f32,
+                        ]
+                    })
+                    .collect()
+            }
+            Shape::Disc { center, radius } => {
+                let golden_angle = TAU * (1. - 1. / 1.618_034);
+                (0..count)
+                    .map(|i| {
+                        let r = radius * t(i).sqrt();
+                        let angle = i as f32 * golden_angle;
+                        [center[0] + r * angle.cos(), center[1] + r * angle.sin()]
+                    })
+                    .collect()
+            }
+            Shape::Ring { center, radius } => (0..count)
+                .map(|i| {
+                    let angle = TAU * i as f32 / count as f32;
+                    [center[0] + radius * angle.cos(), center[1] + radius * angle.sin()]
+                })
+                .collect(),
+            Shape::Line { start, end } => (0..count)
+                .map(|i| {
+                    [
+                        start[0] + (end[0] - start[0]) * t(i),
+                        start[1] + (end[1] - start[1]) * t(i),
+                    ]
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Scene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        let scene: Self = toml::from_str(source)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Checks for values that would break the simulation rather than just look odd, like
+    /// a timestep or zoom of zero or a shape with no size.
+    pub fn validate(&self) -> Result<(), SceneError> {
+        positive("simulation.timestep", self.simulation.timestep)?;
+        if let Some(adaptive) = &self.simulation.adaptive {
+            positive(
+                "simulation.adaptive.max_displacement",
+                adaptive.max_displacement,
+            )?;
+            positive("simulation.adaptive.accuracy", adaptive.accuracy)?;
+            positive("simulation.adaptive.min", adaptive.min)?;
+        }
+        positive("camera.zoom", self.camera.zoom)?;
+
+        for (i, group) in self.groups.iter().enumerate() {
+            let name = |field: &str| format!("group {} {}", i + 1, field);
+            if group.count == 0 {
+                return Err(SceneError::Invalid(format!(
+                    "{} must be at least 1",
+                    name("count")
+                )));
+            }
+            positive(&name("mass"), group.attributes.mass)?;
+            group.shape.validate(name)?;
+        }
+
+        for (i, collider) in self.colliders.iter().enumerate() {
+            if let Collider::Circle { radius, .. } = collider {
+                positive(&format!("collider {} radius", i + 1), *radius)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Spreads `count` particles over the groups in proportion to their current counts.
+    /// Without any groups the default grid is replaced by one of `count` particles.
+    pub fn set_particle_count(&mut self, count: usize) {
+        let total: usize = self.groups.iter().map(|group| group.count).sum();
+        if total == 0 {
+            let side = SIDE_LENGTH as f32;
+            self.groups = vec![ParticleGroup {
+                shape: Shape::Grid {
+                    min: [0., 0.],
+                    max: [side, side],
+                },
+                count,
+                velocity: [0., 0.],
+                attributes: ParticleAttributes::default(),
+            }];
+            return;
+        }
+
+        let mut assigned = 0;
+        let mut before = 0;
+        for group in &mut self.groups {
+            // Rounding the running total keeps the sum exact
+            before += group.count;
+            let end = (before as f64 * count as f64 / total as f64).round() as usize;
+            group.count = end - assigned;
+            assigned = end;
+        }
+    }
+
+    /// Reseeds every random group, the nth group gets `seed + n`.
+    pub fn reseed(&mut self, seed: u64) {
+        for (i, group) in self.groups.iter_mut().enumerate() {
+            if let Some(group_seed) = group.shape.seed_mut() {
+                *group_seed = seed.wrapping_add(i as u64);
+            }
+        }
+    }
+
+    /// Two rotating disc galaxies, each held together by an attractor at its core, falling
+    /// past each other. `seed` picks the particles, the second galaxy uses `seed + 1`.
+    pub fn galaxy_collision(count_per_galaxy: usize, seed: u64) -> Self {
+        let side = SIDE_LENGTH as f32;
+        let radius = side / 8.;
+        // Off centre so the cores pass each other instead of merging head on
+        let galaxies = [
+            ([side * 0.3, side * 0.45], [40., 0.], false),
+            ([side * 0.7, side * 0.55], [-40., 0.], true),
+        ];
+
+        let mut scene = Self {
+            attractors: Vec::new(),
+            ..Self::default()
+        };
+        for (i, (center, velocity, clockwise)) in galaxies.into_iter().enumerate() {
+            let core = Attractor {
+                position: center,
+                velocity,
+                ..Attractor::default()
+            };
+            scene.groups.push(ParticleGroup {
+                shape: Shape::ExponentialDisc {
+                    center,
+                    scale_length: radius / 3.,
+                    radius,
+                    strength: core.strength,
+                    falloff: core.falloff,
+                    clockwise,
+                    seed: seed.wrapping_add(i as u64),
+                },
+                count: count_per_galaxy,
+                velocity,
+                attributes: ParticleAttributes::default(),
+            });
+            scene.attractors.push(core);
+        }
+        scene
+    }
+
+    /// Every particle of every group, or the default grid without any groups.
+    pub fn particles(&self) -> Vec<ParticleInstance> {
+        if self.groups.is_empty() {
+            return ParticleInstance::grid(SIDE_LENGTH);
+        }
+
+        let timestep = self.simulation.timestep;
+        self.groups
+            .iter()
+            .flat_map(|group| {
+                group.samples().into_iter().map(move |sample| {
+                    let [x, y] = sample.position;
+                    let [vx, vy] = sample.velocity;
+                    ParticleInstance::new(x, y)
+                        .with_velocity(vx * timestep, vy * timestep)
+                        .with_attributes(group.attributes)
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_scenes_parse() {
+        for source in [
+            include_str!("../../scenes/orbits.toml"),
+            include_str!("../../scenes/collision.toml"),
+        ] {
+            let scene = Scene::from_toml(source).unwrap();
+            assert!(!scene.groups.is_empty());
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        for source in [
+            "[camera]\nzom = 2.0",
+            "[boundary]\nmode = \"wrap\"\nrestitutoin = 0.5",
+            "[[group]]\nshape = \"disc\"\ncenter = [0.0, 0.0]\nradius = 1.0\ncount = 1\nsize = 2.0",
+        ] {
+            assert!(
+                matches!(Scene::from_toml(source), Err(SceneError::Parse(_))),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for source in [
+            "[camera]\nzoom = 0.0",
+            "[simulation]\ntimestep = -0.1",
+            "[[group]]\nshape = \"disc\"\ncenter = [0.0, 0.0]\nradius = 1.0\ncount = 0",
+            "[[group]]\nshape = \"gaussian\"\ncenter = [0.0, 0.0]\nsigma = 0.0\ncount = 10",
+            "[[group]]\nshape = \"exponential_disc\"\ncenter = [0.0, 0.0]\nscale_length = 1.0\nradius = 0.0\ncount = 10",
+        ] {
+            assert!(matches!(Scene::from_toml(source), Err(SceneError::Invalid(_))), "{}", source);
+        }
+    }
+}
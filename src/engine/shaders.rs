@@ -0,0 +1,122 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use wgpu::{naga, Device, ErrorFilter};
+
+/// Where the engine's shaders live in a source checkout.
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/engine");
+
+/// How often the files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The shaders that can be swapped while running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderFile {
+    /// `particle_compute.wgsl`, the physics kernels.
+    ParticleCompute,
+    /// `particle_shader.wgsl`, drawing the particles.
+    ParticleShader,
+}
+
+impl ShaderFile {
+    const ALL: [ShaderFile; 2] = [ShaderFile::ParticleCompute, ShaderFile::ParticleShader];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ShaderFile::ParticleCompute => "particle_compute.wgsl",
+            ShaderFile::ParticleShader => "particle_shader.wgsl",
+        }
+    }
+}
+
+struct Watched {
+    file: ShaderFile,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// Watches shader sources for changes by polling their modification times.
+pub struct ShaderWatcher {
+    files: Vec<Watched>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    /// Watches the shaders in `directory`, changes from now on are reported.
+    pub fn new(directory: impl AsRef<Path>) -> Self {
+        let files = ShaderFile::ALL
+            .into_iter()
+            .map(|file| {
+                let path = directory.as_ref().join(file.file_name());
+                Watched {
+                    file,
+                    modified: modified(&path),
+                    path,
+                }
+            })
+            .collect();
+
+        Self {
+            files,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// The shaders that changed since the last call along with their new source.
+    /// Files that can't be read are skipped and logged.
+    pub fn poll(&mut self) -> Vec<(ShaderFile, String)> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for watched in &mut self.files {
+            let modified = modified(&watched.path);
+            if modified.is_none() || modified == watched.modified {
+                continue;
+            }
+            watched.modified = modified;
+
+            match std::fs::read_to_string(&watched.path) {
+                Ok(source) => changed.push((watched.file, source)),
+                Err(e) => log::error!("Failed to read {}: {}", watched.path.display(), e),
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Parses and validates WGSL with naga, the error is formatted with the offending source lines.
+pub fn validate(
+    source: &str,
+    file: ShaderFile,
+) -> Result<(naga::Module, naga::valid::ModuleInfo), String> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| e.emit_to_string_with_path(source, file.file_name()))?;
+
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| e.emit_to_string_with_path(source, file.file_name()))?;
+    Ok((module, info))
+}
+
+/// Runs `create` in a validation error scope, so invalid pipelines come back as an error
+/// instead of reaching the uncaptured error handler, which panics.
+pub(crate) fn catch_errors<T>(device: &Device, create: impl FnOnce() -> T) -> Result<T, String> {
+    device.push_error_scope(ErrorFilter::Validation);
+    let value = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(e) => Err(e.to_string()),
+        None => Ok(value),
+    }
+}
@@ -0,0 +1,277 @@
+use std::path::Path;
+
+use bytemuck::{Pod, Zeroable};
+use image::{imageops::FilterType, ImageResult, RgbaImage};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+    BufferBindingType, BufferUsages, Device, FilterMode, ImageCopyTextureBase, Origin3d, Queue,
+    SamplerBindingType, SamplerDescriptor, ShaderStages, TextureDescriptor, TextureSampleType,
+    TextureUsages, TextureViewDescriptor, TextureViewDimension,
+};
+
+use crate::Error;
+
+/// A sprite sheet of equally sized frames laid out in a grid.
+///
+/// Each species owns `frames_per_species` consecutive frames, a particle steps
+/// through them over its lifetime. Particles without a lifetime use the first one.
+#[derive(Clone)]
+pub struct SpriteAtlas {
+    image: RgbaImage,
+    columns: u32,
+    rows: u32,
+    frames_per_species: u32,
+}
+
+impl SpriteAtlas {
+    /// The particle sprite shipped with the engine.
+    pub fn builtin() -> ImageResult<Self> {
+        let particle_image = image::load_from_memory(include_bytes!("particle.png"))?;
+        Ok(Self::from_image(particle_image.to_rgba8(), 1, 1))
+    }
+
+    pub fn load(path: impl AsRef<Path>, columns: u32, rows: u32) -> ImageResult<Self> {
+        Ok(Self::from_image(image::open(path)?.to_rgba8(), columns, rows))
+    }
+
+    pub fn from_image(image: RgbaImage, columns: u32, rows: u32) -> Self {
+        Self {
+            image,
+            columns: columns.max(1),
+            rows: rows.max(1),
+            frames_per_species: 1,
+        }
+    }
+
+    pub fn with_frames_per_species(mut self, frames: u32) -> Self {
+        self.frames_per_species = frames.max(1);
+        self
+    }
+
+    /// Downsamples every frame on its own so neighbouring frames don't bleed into each other.
+    /// Each level has the texture's own mip extent, with the frames laid out on the same
+    /// fractions of it the shader samples, and the chain stops once a frame would be under a pixel.
+    fn mip_chain(&self) -> Vec<RgbaImage> {
+        let (width, height) = self.image.dimensions();
+        let mut levels = vec![self.image.clone()];
+
+        let mut level = 1;
+        while width >> level >= self.columns && height >> level >= self.rows {
+            let (level_width, level_height) = (width >> level, height >> level);
+            let mut mip = RgbaImage::new(level_width, level_height);
+
+            for row in 0..self.rows {
+                for column in 0..self.columns {
+                    let (x, frame_width) = cell(column, self.columns, width);
+                    let (y, frame_height) = cell(row, self.rows, height);
+                    let frame =
+                        image::imageops::crop_imm(&self.image, x, y, frame_width, frame_height)
+                            .to_image();
+
+                    let (x, cell_width) = cell(column, self.columns, level_width);
+                    let (y, cell_height) = cell(row, self.rows, level_height);
+                    let frame = image::imageops::resize(
+                        &frame,
+                        cell_width,
+                        cell_height,
+                        FilterType::Triangle,
+                    );
+                    image::imageops::replace(&mut mip, &frame, x as i64, y as i64);
+                }
+            }
+
+            levels.push(mip);
+            level += 1;
+        }
+
+        levels
+    }
+}
+
+/// The start and length of cell `index` when `length` texels are split into `count` cells.
+fn cell(index: u32, count: u32, length: u32) -> (u32, u32) {
+    let start = index * length / count;
+    (start, (index + 1) * length / count - start)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod)]
+struct AtlasUniform {
+    columns: u32,
+    rows: u32,
+    frames_per_species: u32,
+    _padding: u32,
+}
+
+/// The GPU side of a [`SpriteAtlas`], bound as group 0 of the particle pipeline.
+pub struct ParticleSprite {
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+}
+
+impl ParticleSprite {
+    /// Fails when the atlas is larger than the biggest texture of the device.
+    pub fn new(atlas: &SpriteAtlas, device: &Device, queue: &Queue) -> Result<Self, Error> {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Particle Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = Self::create_bind_group(atlas, &bind_group_layout, device, queue)?;
+
+        Ok(Self {
+            bind_group_layout,
+            bind_group,
+        })
+    }
+
+    pub fn group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// Keeps the current atlas when the new one is too large, see [`ParticleSprite::new`].
+    pub fn set_atlas(
+        &mut self,
+        atlas: &SpriteAtlas,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<(), Error> {
+        self.bind_group = Self::create_bind_group(atlas, &self.bind_group_layout, device, queue)?;
+        Ok(())
+    }
+
+    fn create_bind_group(
+        atlas: &SpriteAtlas,
+        layout: &BindGroupLayout,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<BindGroup, Error> {
+        // The mip levels are all smaller than the atlas itself
+        let max = device.limits().max_texture_dimension_2d;
+        for (limit, required) in [
+            ("sprite atlas width", atlas.image.width()),
+            ("sprite atlas height", atlas.image.height()),
+        ] {
+            if required > max {
+                return Err(Error::LimitsExceeded {
+                    limit,
+                    required: required as u64,
+                    supported: max as u64,
+                });
+            }
+        }
+
+        let levels = atlas.mip_chain();
+
+        let particle_texture = device.create_texture(&TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: atlas.image.width(),
+                height: atlas.image.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            label: Some("particle_texture"),
+            view_formats: &[],
+        });
+
+        for (mip_level, level) in levels.iter().enumerate() {
+            queue.write_texture(
+                ImageCopyTextureBase {
+                    mip_level: mip_level as u32,
+                    origin: Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &particle_texture,
+                },
+                level,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level.width()),
+                    rows_per_image: Some(level.height()),
+                },
+                wgpu::Extent3d {
+                    width: level.width(),
+                    height: level.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let particle_view = particle_texture.create_view(&TextureViewDescriptor::default());
+        let particle_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Sprite Atlas Uniform"),
+            contents: bytemuck::cast_slice(&[AtlasUniform {
+                columns: atlas.columns,
+                rows: atlas.rows,
+                frames_per_species: atlas.frames_per_species,
+                _padding: 0,
+            }]),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        Ok(device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Particle Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&particle_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&particle_sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: uniform.as_entire_binding(),
+                },
+            ],
+        }))
+    }
+}
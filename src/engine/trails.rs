@@ -0,0 +1,327 @@
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendFactor, BlendOperation, BlendState, BufferBindingType, BufferUsages, Color,
+    CommandEncoder, Device, FilterMode, Operations, PipelineLayout, PipelineLayoutDescriptor,
+    RenderPass, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    SamplerBindingType, SamplerDescriptor, ShaderModule, ShaderStages, StoreOp, TextureDescriptor,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+    TextureViewDimension,
+};
+use winit::dpi::PhysicalSize;
+
+use super::post::fullscreen_pipeline;
+
+/// The trails accumulate in half floats, 8 bits can't fade a dim trail all the way out
+/// and leave ghosts behind.
+pub const TRAIL_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// `background * (1 - decay) + previous * decay`, the decay is passed as the blend constant.
+const FADE_BLENDING: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::OneMinusConstant,
+        dst_factor: BlendFactor::Constant,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent::REPLACE,
+};
+
+struct Accumulation {
+    view: TextureView,
+    bind_group: BindGroup,
+}
+
+/// Keeps the previous frames around, fading them towards the background instead of clearing.
+///
+/// When drawing straight to the surface the frames accumulate in a [`TRAIL_FORMAT`]
+/// texture which is then composited to the surface. With HDR enabled the HDR
+/// target is faded in place, see [`Trails::set_hdr`].
+pub struct Trails {
+    decay: f32,
+
+    shader: ShaderModule,
+    fade_layout: PipelineLayout,
+    fade_group: BindGroup,
+    fade_pipeline: RenderPipeline,
+
+    texture_layout: BindGroupLayout,
+    blit_pipeline: RenderPipeline,
+    accumulation: Option<Accumulation>,
+
+    /// The target holds garbage, clear it instead of fading on the next frame.
+    stale: bool,
+}
+
+impl Trails {
+    pub fn new(
+        decay: f32,
+        background: Color,
+        size: PhysicalSize<u32>,
+        output_format: TextureFormat,
+        device: &Device,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("trails.wgsl"));
+
+        let fade_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Trail Fade Uniform"),
+            contents: bytemuck::cast_slice(&[
+                background.r as f32,
+                background.g as f32,
+                background.b as f32,
+                background.a as f32,
+            ]),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        let fade_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Trail Fade Bind Group Layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let fade_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Trail Fade Bind Group"),
+            layout: &fade_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: fade_buffer.as_entire_binding(),
+            }],
+        });
+
+        let texture_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Trail Accumulation Bind Group Layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let fade_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Trail Fade Layout"),
+            bind_group_layouts: &[&fade_group_layout],
+            push_constant_ranges: &[],
+        });
+        let blit_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("Trail Blit Layout"),
+            bind_group_layouts: &[&fade_group_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        let fade_pipeline = fullscreen_pipeline(
+            device,
+            &fade_layout,
+            &shader,
+            "fs_fade",
+            TRAIL_FORMAT,
+            Some(FADE_BLENDING),
+        );
+        let blit_pipeline =
+            fullscreen_pipeline(device, &blit_layout, &shader, "fs_blit", output_format, None);
+
+        let accumulation = Some(Self::create_accumulation(size, &texture_layout, device));
+
+        Self {
+            decay: decay.clamp(0., 1.),
+            shader,
+            fade_layout,
+            fade_group,
+            fade_pipeline,
+            texture_layout,
+            blit_pipeline,
+            accumulation,
+            stale: true,
+        }
+    }
+
+    fn create_accumulation(
+        size: PhysicalSize<u32>,
+        layout: &BindGroupLayout,
+        device: &Device,
+    ) -> Accumulation {
+        let view = device
+            .create_texture(&TextureDescriptor {
+                label: Some("Trail Accumulation Texture"),
+                size: wgpu::Extent3d {
+                    width: size.width.max(1),
+                    height: size.height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TRAIL_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Trail Accumulation Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Accumulation { view, bind_group }
+    }
+
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    /// The fraction of the previous frame kept each frame, `0` clears every frame.
+    pub fn set_decay(&mut self, decay: f32) {
+        self.decay = decay.clamp(0., 1.);
+    }
+
+    /// With HDR the trails accumulate in the HDR target, otherwise in a texture of their own.
+    pub fn set_hdr(&mut self, hdr_format: Option<TextureFormat>, size: PhysicalSize<u32>, device: &Device) {
+        let format = hdr_format.unwrap_or(TRAIL_FORMAT);
+        self.fade_pipeline = fullscreen_pipeline(
+            device,
+            &self.fade_layout,
+            &self.shader,
+            "fs_fade",
+            format,
+            Some(FADE_BLENDING),
+        );
+        self.accumulation = match hdr_format {
+            Some(_) => None,
+            None => Some(Self::create_accumulation(
+                size,
+                &self.texture_layout,
+                device,
+            )),
+        };
+        self.stale = true;
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>, device: &Device) {
+        if self.accumulation.is_some() {
+            self.accumulation = Some(Self::create_accumulation(
+                size,
+                &self.texture_layout,
+                device,
+            ));
+        }
+        self.stale = true;
+    }
+
+    /// The texture to draw into when not rendering in HDR.
+    pub fn accumulation_view(&self) -> Option<&TextureView> {
+        self.accumulation.as_ref().map(|a| &a.view)
+    }
+
+    /// Whether the target has to be cleared this frame, resets once asked.
+    pub fn take_stale(&mut self) -> bool {
+        std::mem::replace(&mut self.stale, false)
+    }
+
+    /// Fades `target` towards the background, the particle pass should then load it.
+    pub fn fade(
+        &self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        background: Color,
+        clear: bool,
+    ) {
+        let load = if clear {
+            wgpu::LoadOp::Clear(background)
+        } else {
+            wgpu::LoadOp::Load
+        };
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Trail Fade Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        let decay = self.decay as f64;
+        pass.set_blend_constant(Color {
+            r: decay,
+            g: decay,
+            b: decay,
+            a: decay,
+        });
+        pass.set_pipeline(&self.fade_pipeline);
+        pass.set_bind_group(0, &self.fade_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Copies the accumulated trails onto `output`, returning the pass still open for
+    /// drawing overlays in. Does nothing with HDR, where the HDR target holds the trails.
+    pub fn composite<'e>(
+        &self,
+        encoder: &'e mut CommandEncoder,
+        output: &TextureView,
+    ) -> Option<RenderPass<'e>> {
+        let accumulation = self.accumulation.as_ref()?;
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Trail Composite Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: Operations {
+                    load: wgpu::LoadOp::Clear(Color::BLACK),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.blit_pipeline);
+        pass.set_bind_group(0, &self.fade_group, &[]);
+        pass.set_bind_group(1, &accumulation.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+        Some(pass)
+    }
+}
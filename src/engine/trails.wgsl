@@ -0,0 +1,42 @@
+struct VertexOutput
+{
+    @builtin(position) clip_position : vec4<f32>,
+    @location(0) uv : vec2<f32>,
+}
+
+struct FadeUniform
+{
+    background : vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> fade : FadeUniform;
+
+@group(1) @binding(0)
+var accumulation : texture_2d<f32>;
+@group(1) @binding(1)
+var accumulation_sampler : sampler;
+
+// A single triangle covering the screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index : u32) -> VertexOutput
+{
+    var out : VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.clip_position = vec4<f32>(uv * 2. - 1., 0., 1.);
+    out.uv = vec2<f32>(uv.x, 1. - uv.y);
+    return out;
+}
+
+// Blended with the blend constant set to the decay, pulls the previous frame towards the background
+@fragment
+fn fs_fade(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    return fade.background;
+}
+
+@fragment
+fn fs_blit(in : VertexOutput) -> @location(0) vec4<f32>
+{
+    return textureSample(accumulation, accumulation_sampler, in.uv);
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use winit::dpi::PhysicalSize;
+
+use crate::engine::SceneError;
+
+/// Why an [`Instance`](crate::engine::Instance) couldn't be created, a scene couldn't be run
+/// or a frame couldn't be captured.
+#[derive(Debug)]
+pub enum Error {
+    /// No adapter on any of the backends tried, fallbacks included.
+    NoAdapter { backends: wgpu::Backends },
+    /// The adapter refused to create a device.
+    DeviceRequest(wgpu::RequestDeviceError),
+    /// The window can't be drawn to on any backend.
+    Surface(wgpu::CreateSurfaceError),
+    /// A surface was attached that the adapter the instance runs on can't present to.
+    SurfaceUnsupported,
+    /// A built in asset failed to decode.
+    Asset {
+        name: &'static str,
+        source: image::ImageError,
+    },
+    /// More of the device is needed than it supports, like a particle buffer larger than
+    /// the biggest storage buffer or a capture wider than the biggest texture.
+    LimitsExceeded {
+        limit: &'static str,
+        required: u64,
+        supported: u64,
+    },
+    Scene(SceneError),
+    /// A capture that is empty, or one with trails that isn't the size of the window
+    /// the trails are kept at. `available` is the largest size or the window size.
+    CaptureSize {
+        requested: PhysicalSize<u32>,
+        available: PhysicalSize<u32>,
+    },
+    /// Custom colour scalars that aren't one per particle.
+    ScalarCount { particles: u32, scalars: usize },
+    /// A vector field that isn't at least 1x1, or whose values don't cover it.
+    FieldSize { width: u32, height: u32, values: usize },
+    /// An image couldn't be read, encoded or written.
+    Image(image::ImageError),
+    /// A capture couldn't be copied back from the GPU.
+    Readback(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoAdapter { backends } => {
+                write!(f, "no graphics adapter found on {:?}", backends)
+            }
+            Error::DeviceRequest(e) => write!(f, "failed to create the device: {}", e),
+            Error::Surface(e) => write!(f, "failed to create the window surface: {}", e),
+            Error::SurfaceUnsupported => write!(f, "the adapter can't present to the window"),
+            Error::Asset { name, source } => write!(f, "failed to decode {}: {}", name, source),
+            Error::LimitsExceeded {
+                limit,
+                required,
+                supported,
+            } => write!(
+                f,
+                "the {} needed is {} but the device supports at most {}",
+                limit, required, supported
+            ),
+            Error::Scene(e) => e.fmt(f),
+            Error::CaptureSize {
+                requested,
+                available,
+            } if requested.width == 0 || requested.height == 0 => write!(
+                f,
+                "can't capture an empty {}x{} frame",
+                requested.width, requested.height
+            ),
+            Error::CaptureSize {
+                requested,
+                available,
+            } => write!(
+                f,
+                "trails are only kept at {}x{}, they can't be captured at {}x{}",
+                available.width, available.height, requested.width, requested.height
+            ),
+            Error::ScalarCount { particles, scalars } => write!(
+                f,
+                "{} custom scalars given for {} particles, there has to be one per particle",
+                scalars, particles
+            ),
+            Error::FieldSize {
+                width,
+                height,
+                values,
+            } => write!(
+                f,
+                "a {}x{} vector field needs {} values and at least one, not {}",
+                width,
+                height,
+                *width as u64 * *height as u64,
+                values
+            ),
+            Error::Image(e) => write!(f, "image error: {}", e),
+            Error::Readback(e) => write!(f, "failed to read back the capture: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::DeviceRequest(e) => Some(e),
+            Error::Surface(e) => Some(e),
+            Error::Asset { source, .. } => Some(source),
+            Error::Scene(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::NoAdapter { .. }
+            | Error::SurfaceUnsupported
+            | Error::LimitsExceeded { .. }
+            | Error::CaptureSize { .. }
+            | Error::ScalarCount { .. }
+            | Error::FieldSize { .. }
+            | Error::Readback(_) => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        Error::DeviceRequest(e)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for Error {
+    fn from(e: wgpu::CreateSurfaceError) -> Self {
+        Error::Surface(e)
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(e: SceneError) -> Self {
+        Error::Scene(e)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        Error::Image(e)
+    }
+}
@@ -1,3 +1,6 @@
 pub mod engine;
+mod error;
+
+pub use error::Error;
 
 pub const SIDE_LENGTH : usize = 2500;
\ No newline at end of file
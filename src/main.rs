@@ -1,18 +1,169 @@
-use std::time::Instant;
+mod cli;
 
-use phys_engine::{engine::{Instance, Vertex}, SIDE_LENGTH};
-use winit::{dpi::PhysicalSize, event::{self, ElementState, Event, KeyEvent, WindowEvent}, event_loop::EventLoop, keyboard::{KeyCode, PhysicalKey}, window::WindowBuilder};
+use std::{sync::Arc, time::Instant};
+
+use clap::Parser;
+use cli::Args;
+use phys_engine::engine::{describe_adapter, list_adapters, AdaptiveTimestep, Instance, Recorder, VectorField, Vertex};
+use winit::{event::{self, ElementState, Event, KeyEvent, WindowEvent}, event_loop::{ControlFlow, EventLoop}, keyboard::{KeyCode, PhysicalKey}, window::{Fullscreen, WindowBuilder}};
 
 fn main() {
-    pollster::block_on(run())
+    let args = Args::parse();
+    env_logger::init();
+    if args.list_adapters
+    {
+        for (i, info) in list_adapters(args.backends()).iter().enumerate()
+        {
+            println!("{}: {}", i, describe_adapter(info));
+        }
+        return;
+    }
+
+    if let Err(e) = std::fs::create_dir_all(&args.output)
+    {
+        log::error!("Failed to create {}: {}", args.output.display(), e);
+    }
+
+    match args.headless
+    {
+        Some(steps) => pollster::block_on(run_headless(args, steps)),
+        None => pollster::block_on(run(args)),
+    }
 }
 
-async fn run()
+/// Reports why the engine couldn't start and exits.
+fn fail(e : phys_engine::Error) -> !
+{
+    log::error!("{}", e);
+    std::process::exit(1);
+}
+
+/// Loads the scene and applies the overrides and recording from the command line.
+fn setup(instance : &mut Instance, args : &Args)
+{
+    // F5 reloads the file as written, without the overrides
+    if let Some(path) = &args.scene
+    {
+        if let Err(e) = instance.load_scene(path)
+        {
+            log::error!("{}: {}", path.display(), e);
+        }
+    }
+
+    if args.count.is_some() || args.seed.is_some() || args.integrator.is_some() || args.adaptive
+    {
+        let mut scene = instance.scene().clone();
+        if let Some(count) = args.count
+        {
+            scene.set_particle_count(count);
+        }
+        if let Some(integrator) = args.integrator
+        {
+            scene.simulation.integrator = integrator.into();
+        }
+        if args.adaptive && scene.simulation.adaptive.is_none()
+        {
+            scene.simulation.adaptive = Some(AdaptiveTimestep::default());
+        }
+        if let Some(seed) = args.seed
+        {
+            scene.reseed(seed);
+        }
+        if let Err(e) = instance.set_scene(scene)
+        {
+            log::error!("{}", e);
+        }
+    }
+
+    instance.set_diagnostics(args.diagnostics);
+    instance.set_frame_limit(args.fps_limit);
+    instance.set_shader_hot_reload(args.watch_shaders);
+
+    if let Some(path) = &args.field
+    {
+        match VectorField::load(path, args.field_range)
+        {
+            Ok(field) =>
+            {
+                let boundary = &instance.scene().boundary;
+                let field = field.with_bounds(boundary.min, boundary.max);
+                instance.set_vector_field(Some(field));
+            }
+            Err(e) => log::error!("Failed to load {}: {}", path.display(), e),
+        }
+    }
+
+    if let Some(every) = args.record_every
+    {
+        let path = instance.output_path("frames");
+        match Recorder::png_sequence(&path, instance.size(), every)
+        {
+            Ok(recorder) => instance.start_recording(recorder),
+            Err(e) => log::error!("Failed to start recording to {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Steps the simulation without a window, then saves the last frame.
+async fn run_headless(args : Args, steps : u64)
+{
+    let mut instance = match Instance::new_headless(args.size, args.instance_options()).await
+    {
+        Ok(instance) => instance,
+        Err(e) => fail(e),
+    };
+    setup(&mut instance, &args);
+
+    log::info!("Running {} particles for {} steps", instance.particle_count(), steps);
+    for _ in 0..steps
+    {
+        if let Some(deadline) = instance.frame_deadline()
+        {
+            std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+        let start = Instant::now();
+        instance.update();
+        if let Err(e) = instance.render()
+        {
+            log::error!("{}", e);
+            break;
+        }
+        instance.frametime(start.elapsed().as_secs_f32());
+    }
+    instance.stop_recording();
+
+    let stats = instance.frame_stats();
+    log::info!("{:.2} ms median, {:.2} ms p99", stats.median * 1000., stats.p99 * 1000.);
+
+    if let (Some(initial), Some(latest)) = (instance.initial_diagnostics(), instance.diagnostics())
+    {
+        log::info!("Step {}: {:?}", latest.step, latest);
+        log::info!(
+            "Energy drift {:+.4}% since step {}",
+            (latest.total_energy() - initial.total_energy()) / initial.total_energy().abs() * 100.,
+            initial.step
+        );
+    }
+
+    let path = instance.output_path(format!("snapshot-{}.png", instance.step()));
+    match instance.save_screenshot(&path, args.size)
+    {
+        Ok(_) => log::info!("Saved {}", path.display()),
+        Err(e) => log::error!("Failed to save {}: {}", path.display(), e),
+    }
+}
+
+async fn run(args : Args)
 {
-    env_logger::init();
     let event_loop = EventLoop::new().unwrap();
-    let window = WindowBuilder::new().with_inner_size(PhysicalSize::new(200, 200)).build(&event_loop).unwrap();
-    let mut instance = Instance::new(&window).await;
+    let fullscreen = args.fullscreen.then_some(Fullscreen::Borderless(None));
+    let window = Arc::new(WindowBuilder::new().with_inner_size(args.size).with_fullscreen(fullscreen).build(&event_loop).unwrap());
+    let mut instance = match Instance::new(window.clone(), args.instance_options()).await
+    {
+        Ok(instance) => instance,
+        Err(e) => fail(e),
+    };
+    setup(&mut instance, &args);
 
     let _ = event_loop.run(move |event, control_flow| {
         match event
@@ -36,8 +187,24 @@ async fn run()
                 WindowEvent::RedrawRequested =>
                 {
                     let start = Instant::now();
-                    instance.window().set_title(&format!("Rendering {} particles at {} fps", SIDE_LENGTH * SIDE_LENGTH, instance.estimate_fps()));
-                    instance.window().request_redraw();
+                    if let Some(window) = instance.window()
+                    {
+                        let stats = instance.frame_stats();
+                        // GPU time when timestamp queries are supported, the engine's CPU time otherwise
+                        let work = match instance.fps().gpu_stats()
+                        {
+                            Some(gpu) => format!("{:.2} ms gpu", gpu.mean * 1000.),
+                            None => format!("{:.2} ms cpu", instance.fps().cpu_stats().mean * 1000.),
+                        };
+                        window.set_title(&format!(
+                            "Rendering {} particles at {:.0} fps ({:.2} ms median, {:.2} ms p99, {})",
+                            instance.particle_count(),
+                            stats.fps(),
+                            stats.median * 1000.,
+                            stats.p99 * 1000.,
+                            work
+                        ));
+                    }
                     instance.update();
                     
                     match instance.render()
@@ -62,6 +229,31 @@ async fn run()
                 }
                 _ => {}
             },
+            // The surface goes away while suspended on some platforms, the simulation waits until it is back
+            Event::Suspended =>
+            {
+                instance.detach_surface();
+            },
+            Event::Resumed if !instance.has_surface() =>
+            {
+                if let Err(e) = instance.attach_surface(window.clone())
+                {
+                    fail(e);
+                }
+            },
+            // Redraws once the frame limit allows, waiting without spinning until then
+            Event::AboutToWait => match instance.frame_deadline()
+            {
+                Some(deadline) if deadline > Instant::now() => control_flow.set_control_flow(ControlFlow::WaitUntil(deadline)),
+                _ =>
+                {
+                    control_flow.set_control_flow(ControlFlow::Wait);
+                    if let Some(window) = instance.window()
+                    {
+                        window.request_redraw();
+                    }
+                }
+            },
             _ => {},
         }
     });